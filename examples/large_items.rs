@@ -0,0 +1,48 @@
+// Copyright 2021 Martin Pool
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Compare sending large items by value with sending them boxed.
+//!
+//! An item sent by value is copied into the channel by the worker, and out of
+//! it by the consumer. A boxed item is allocated once and only the pointer is
+//! copied.
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use readahead_iterator::Readahead;
+
+const N: usize = 200_000;
+const BUFFER_SIZE: usize = 16;
+const ITEM_SIZE: usize = 16 << 10;
+
+type Item = [u8; ITEM_SIZE];
+
+fn make(i: usize) -> Item {
+    let mut item = [0; ITEM_SIZE];
+    item[i % ITEM_SIZE] = i as u8;
+    item
+}
+
+fn check(item: &Item) -> u64 {
+    black_box(item[0] as u64 + item[ITEM_SIZE - 1] as u64)
+}
+
+pub fn main() {
+    let start = Instant::now();
+    let sum: u64 = Readahead::new((0..N).map(make), BUFFER_SIZE)
+        .map(|item| check(&item))
+        .sum();
+    println!("by value: {:?} (checksum {})", start.elapsed(), sum);
+
+    let start = Instant::now();
+    let sum: u64 = Readahead::new((0..N).map(|i| Box::new(make(i))), BUFFER_SIZE)
+        .map(|item| check(&item))
+        .sum();
+    println!("boxed:    {:?} (checksum {})", start.elapsed(), sum);
+}
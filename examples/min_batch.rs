@@ -0,0 +1,48 @@
+// Copyright 2021 Martin Pool
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Compare waking the consumer for every item with waiting for a minimum
+//! batch, for a stream of small, cheap items.
+//!
+//! The consumer is faster than the worker, so without a minimum batch it
+//! usually finds the buffer empty and waits for the next single item. With a
+//! minimum batch it waits less often, for more items each time.
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use readahead_iterator::ReadaheadBuilder;
+
+const N: u64 = 2_000_000;
+const BUFFER_SIZE: usize = 256;
+
+fn work(i: u64, rounds: u64) -> u64 {
+    (0..rounds).fold(i, |acc, j| black_box(acc.wrapping_mul(31).wrapping_add(j)))
+}
+
+fn run(min_batch: usize) {
+    let start = Instant::now();
+    let sum: u64 = ReadaheadBuilder::new(BUFFER_SIZE)
+        .min_deliver_batch(min_batch)
+        .spawn((0..N).map(|i| work(i, 40)))
+        .unwrap()
+        .map(|i| work(i, 10))
+        .fold(0, u64::wrapping_add);
+    println!(
+        "min_deliver_batch({:>3}): {:?} (checksum {})",
+        min_batch,
+        start.elapsed(),
+        sum
+    );
+}
+
+pub fn main() {
+    for &min_batch in &[1, 8, 64, 256] {
+        run(min_batch);
+    }
+}
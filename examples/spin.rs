@@ -0,0 +1,50 @@
+// Copyright 2021 Martin Pool
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Compare blocking straight away with spinning before blocking, when the
+//! worker sends to a small buffer that the consumer drains quickly.
+//!
+//! Each item takes a little work on both sides, so the buffer is often full
+//! for only a moment. Spinning is most likely to help with a small buffer on
+//! a machine with spare cores, where the consumer runs while the worker
+//! spins. On a single core, or with a slow consumer, it only burns CPU, and
+//! this example shows it getting slower as the spin count grows.
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use readahead_iterator::ReadaheadBuilder;
+
+const N: u64 = 2_000_000;
+const BUFFER_SIZE: usize = 4;
+
+fn work(i: u64) -> u64 {
+    (0..20).fold(i, |acc, j| black_box(acc.wrapping_mul(31).wrapping_add(j)))
+}
+
+fn run(spin: usize) {
+    let start = Instant::now();
+    let sum: u64 = ReadaheadBuilder::new(BUFFER_SIZE)
+        .spin_before_block(spin)
+        .spawn((0..N).map(work))
+        .unwrap()
+        .map(work)
+        .fold(0, u64::wrapping_add);
+    println!(
+        "spin_before_block({:>5}): {:?} (checksum {})",
+        spin,
+        start.elapsed(),
+        sum
+    );
+}
+
+pub fn main() {
+    for &spin in &[0, 100, 1000, 10000] {
+        run(spin);
+    }
+}
@@ -0,0 +1,116 @@
+// Copyright 2021 Martin Pool
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Acknowledging items back to the worker once they've been processed.
+
+use std::ops::{Deref, DerefMut};
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+/// An item that tells the worker when the consumer is finished with it.
+///
+/// Created by [`IntoReadahead::readahead_acked`](crate::IntoReadahead::readahead_acked).
+/// Dropping the `Ack` acknowledges the item: the worker's `on_ack` callback is
+/// then called with the item's index.
+#[derive(Debug)]
+pub struct Ack<T> {
+    item: T,
+    index: usize,
+    sender: Sender<usize>,
+}
+
+impl<T> Ack<T> {
+    /// The position of this item in the stream, counting from zero, as passed
+    /// to `on_ack`.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+}
+
+impl<T> Deref for Ack<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.item
+    }
+}
+
+impl<T> DerefMut for Ack<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.item
+    }
+}
+
+impl<T> Drop for Ack<T> {
+    fn drop(&mut self) {
+        // If the worker has gone, nobody's waiting for the ack.
+        let _ = self.sender.send(self.index);
+    }
+}
+
+/// Wraps items in [`Ack`]s, and calls `on_ack` on the worker as they're
+/// acknowledged.
+pub(crate) struct Acked<I, F: FnMut(usize)> {
+    inner: I,
+    on_ack: F,
+    /// Cloned into each `Ack`; `None` once the worker is waiting for the last
+    /// acks.
+    sender: Option<Sender<usize>>,
+    receiver: Receiver<usize>,
+    next_index: usize,
+}
+
+impl<I, F> Acked<I, F>
+where
+    I: Iterator,
+    F: FnMut(usize),
+{
+    pub(crate) fn new(inner: I, on_ack: F) -> Self {
+        let (sender, receiver) = channel();
+        Acked {
+            inner,
+            on_ack,
+            sender: Some(sender),
+            receiver,
+            next_index: 0,
+        }
+    }
+}
+
+impl<I, F> Iterator for Acked<I, F>
+where
+    I: Iterator,
+    F: FnMut(usize),
+{
+    type Item = Ack<I::Item>;
+
+    fn next(&mut self) -> Option<Ack<I::Item>> {
+        while let Ok(index) = self.receiver.try_recv() {
+            (self.on_ack)(index);
+        }
+        let item = self.inner.next()?;
+        let index = self.next_index;
+        self.next_index += 1;
+        Some(Ack {
+            item,
+            index,
+            sender: self.sender.clone()?,
+        })
+    }
+}
+
+impl<I, F: FnMut(usize)> Drop for Acked<I, F> {
+    fn drop(&mut self) {
+        // This is dropped on the worker after the end of the stream is sent,
+        // or the consumer hangs up: wait until every outstanding item, whether
+        // held by the consumer or still in the buffer, is dropped.
+        self.sender = None;
+        for index in self.receiver.iter() {
+            (self.on_ack)(index);
+        }
+    }
+}
@@ -0,0 +1,197 @@
+// Copyright 2021 Martin Pool
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Iterator adaptors that run on the worker thread, for combinators that have
+//! no equivalent in the standard library.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+use std::time::{Duration, Instant};
+
+use crate::Readahead;
+
+/// Groups consecutive items with equal keys.
+pub(crate) struct GroupBy<I: Iterator, F, K> {
+    inner: I,
+    key_fn: F,
+    /// The first item of the next group, and its key.
+    next: Option<(K, I::Item)>,
+}
+
+impl<I, F, K> GroupBy<I, F, K>
+where
+    I: Iterator,
+    F: FnMut(&I::Item) -> K,
+{
+    pub(crate) fn new(inner: I, key_fn: F) -> Self {
+        GroupBy {
+            inner,
+            key_fn,
+            next: None,
+        }
+    }
+}
+
+impl<I, F, K> Iterator for GroupBy<I, F, K>
+where
+    I: Iterator,
+    F: FnMut(&I::Item) -> K,
+    K: PartialEq,
+{
+    type Item = (K, Vec<I::Item>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, first) = match self.next.take() {
+            Some(next) => next,
+            None => {
+                let item = self.inner.next()?;
+                ((self.key_fn)(&item), item)
+            }
+        };
+        let mut group = vec![first];
+        for item in &mut self.inner {
+            let item_key = (self.key_fn)(&item);
+            if item_key == key {
+                group.push(item);
+            } else {
+                self.next = Some((item_key, item));
+                break;
+            }
+        }
+        Some((key, group))
+    }
+}
+
+/// Reorders items within a bounded window, emitting the smallest item in
+/// the window each time it's full.
+pub(crate) struct Sorted<I: Iterator> {
+    inner: I,
+    heap: BinaryHeap<Reverse<I::Item>>,
+    window: usize,
+}
+
+impl<I> Sorted<I>
+where
+    I: Iterator,
+    I::Item: Ord,
+{
+    pub(crate) fn new(inner: I, window: usize) -> Self {
+        Sorted {
+            inner,
+            heap: BinaryHeap::with_capacity(window + 1),
+            window,
+        }
+    }
+}
+
+impl<I> Iterator for Sorted<I>
+where
+    I: Iterator,
+    I::Item: Ord,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        while self.heap.len() <= self.window {
+            match self.inner.next() {
+                Some(item) => self.heap.push(Reverse(item)),
+                None => break,
+            }
+        }
+        self.heap.pop().map(|Reverse(item)| item)
+    }
+}
+
+/// Overlapping windows of consecutive items.
+pub(crate) struct Windows<I: Iterator> {
+    inner: I,
+    window: VecDeque<I::Item>,
+    size: usize,
+}
+
+impl<I> Windows<I>
+where
+    I: Iterator,
+    I::Item: Clone,
+{
+    pub(crate) fn new(inner: I, size: usize) -> Self {
+        assert!(size > 0, "window size must be non-zero");
+        Windows {
+            inner,
+            window: VecDeque::with_capacity(size),
+            size,
+        }
+    }
+}
+
+impl<I> Iterator for Windows<I>
+where
+    I: Iterator,
+    I::Item: Clone,
+{
+    type Item = Vec<I::Item>;
+
+    fn next(&mut self) -> Option<Vec<I::Item>> {
+        if self.window.len() == self.size {
+            self.window.pop_front();
+        }
+        while self.window.len() < self.size {
+            self.window.push_back(self.inner.next()?);
+        }
+        Some(self.window.iter().cloned().collect())
+    }
+}
+
+/// Batches items that arrive from `source` within a time window.
+pub(crate) struct CoalesceTime<T: Send + 'static> {
+    source: Readahead<T>,
+    window: Duration,
+    max: usize,
+    /// True once the source has ended, after any partial batch.
+    ended: bool,
+}
+
+impl<T: Send + 'static> CoalesceTime<T> {
+    pub(crate) fn new(source: Readahead<T>, window: Duration, max: usize) -> Self {
+        assert!(max > 0, "batch size must be non-zero");
+        CoalesceTime {
+            source,
+            window,
+            max,
+            ended: false,
+        }
+    }
+}
+
+impl<T: Send + 'static> Iterator for CoalesceTime<T> {
+    type Item = Vec<T>;
+
+    fn next(&mut self) -> Option<Vec<T>> {
+        if self.ended {
+            return None;
+        }
+        let first = self.source.next()?;
+        let deadline = Instant::now() + self.window;
+        let mut batch = vec![first];
+        while batch.len() < self.max {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            match self.source.recv_timeout(deadline - now) {
+                Some(Some(item)) => batch.push(item),
+                Some(None) => {
+                    self.ended = true;
+                    break;
+                }
+                None => break,
+            }
+        }
+        Some(batch)
+    }
+}
@@ -0,0 +1,1215 @@
+// Copyright 2021 Martin Pool
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Configuration of the readahead worker thread.
+
+use std::any::Any;
+use std::collections::BinaryHeap;
+use std::fmt;
+use std::io;
+use std::mem;
+use std::sync::mpsc::{channel, sync_channel};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::clock::{Clock, SystemClock};
+use crate::diagnostics::{DegenerateCheck, OrderingCheck};
+use crate::held::Held;
+use crate::parallel::{map_chunks_inline, map_parallel_chunks};
+use crate::shared::Shared;
+use crate::worker::{Worker, WorkerSender};
+use crate::{JoinOnDrop, Readahead};
+
+/// The name given to worker threads unless another is configured.
+pub(crate) const DEFAULT_THREAD_NAME: &str = "readahead_iterator";
+
+/// The stack size set by [`ReadaheadBuilder::small_stack`].
+const SMALL_STACK_SIZE: usize = 64 << 10;
+
+/// A callback observing a panic on the worker thread.
+pub(crate) type PanicHandler = Box<dyn Fn(Box<dyn Any + Send>) + Send>;
+
+/// What the worker does if the inner iterator panics.
+///
+/// Set with [`ReadaheadBuilder::panic_policy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PanicPolicy {
+    /// End the stream cleanly after the items produced before the panic.
+    Swallow,
+    /// Resume the panic on the consumer thread, when it reaches the point in
+    /// the stream where the panic happened.
+    Propagate,
+    /// Abort the whole process immediately, from the worker thread.
+    Abort,
+}
+
+/// The order in which the consumer receives buffered items.
+///
+/// Set with [`ReadaheadBuilder::order`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryOrder {
+    /// Oldest first, preserving the order of the source. This is the default.
+    Fifo,
+    /// Newest first: each `next()` returns the most recently produced of the
+    /// buffered items.
+    Lifo,
+}
+
+/// Combines two items into one.
+pub(crate) type Coalesce<T> = Box<dyn Fn(T, T) -> T + Send>;
+
+/// Nudges a blocked inner iterator to return.
+pub(crate) type Interrupt = Box<dyn FnOnce() + Send>;
+
+/// Called when the inner iterator is exhausted.
+pub(crate) type CompletionHandler = Box<dyn FnOnce() + Send>;
+
+/// Measures the size of an item, in bytes.
+pub(crate) type SizeFn<T> = Box<dyn Fn(&T) -> usize + Send>;
+
+/// Told that readahead doesn't seem to be helping.
+pub(crate) type DegenerateHandler = Box<dyn Fn(&str) + Send>;
+
+/// Told the position of an item that was slow to produce, and how long it took.
+pub(crate) type SlowHandler = Box<dyn Fn(usize, Duration) + Send>;
+
+/// Configures a [`Readahead`] before its worker thread is spawned.
+///
+/// [`Readahead::new`] and [`IntoReadahead::readahead`](crate::IntoReadahead::readahead)
+/// are shortcuts for a builder with default settings.
+///
+/// ```
+/// use readahead_iterator::ReadaheadBuilder;
+///
+/// let sum: u32 = ReadaheadBuilder::new(10)
+///     .name("numbers")
+///     .spawn(1..=10)
+///     .expect("failed to spawn worker")
+///     .sum();
+/// assert_eq!(sum, 55);
+/// ```
+pub struct ReadaheadBuilder<T> {
+    /// Maximum number of buffered items, or `None` if unbounded.
+    buffer_size: Option<usize>,
+    name: Option<String>,
+    stack_size: Option<usize>,
+    on_panic: Option<PanicHandler>,
+    on_complete: Option<CompletionHandler>,
+    panic_policy: Option<PanicPolicy>,
+    join_on_drop: Option<Option<Duration>>,
+    on_degenerate: Option<DegenerateHandler>,
+    drop_in_order: bool,
+    coalesce: Option<Coalesce<T>>,
+    lazy: bool,
+    verify_ordering: bool,
+    linger: Option<Duration>,
+    enabled: bool,
+    byte_limit: Option<(usize, SizeFn<T>)>,
+    interrupt: Option<Interrupt>,
+    spin_before_block: usize,
+    throttle_on_lag: bool,
+    /// Used to spawn the worker, instead of `name` and `stack_size`.
+    thread_builder: Option<thread::Builder>,
+    order: DeliveryOrder,
+    min_batch: Option<usize>,
+    /// The initial and largest buffer sizes, if it grows.
+    adaptive_buffer: Option<(usize, usize)>,
+    /// Holds items for priority delivery, overriding `order`.
+    priority: Option<Box<dyn Held<T>>>,
+    max_memory: Option<usize>,
+    deterministic: bool,
+    deadline: Option<Instant>,
+    max_duration: Option<Duration>,
+    clock: Arc<dyn Clock>,
+    warn_slow: Option<(Duration, SlowHandler)>,
+}
+
+impl<T> ReadaheadBuilder<T>
+where
+    T: Send + 'static,
+{
+    /// Start configuring a readahead that buffers up to `buffer_size` items.
+    pub fn new(buffer_size: usize) -> Self {
+        ReadaheadBuilder {
+            buffer_size: Some(buffer_size),
+            name: None,
+            stack_size: None,
+            on_panic: None,
+            on_complete: None,
+            panic_policy: None,
+            join_on_drop: None,
+            on_degenerate: None,
+            drop_in_order: false,
+            coalesce: None,
+            lazy: false,
+            verify_ordering: false,
+            linger: None,
+            enabled: true,
+            byte_limit: None,
+            interrupt: None,
+            spin_before_block: 0,
+            throttle_on_lag: false,
+            thread_builder: None,
+            order: DeliveryOrder::Fifo,
+            min_batch: None,
+            adaptive_buffer: None,
+            priority: None,
+            max_memory: None,
+            deterministic: false,
+            deadline: None,
+            max_duration: None,
+            clock: Arc::new(SystemClock),
+            warn_slow: None,
+        }
+    }
+
+    /// Start configuring a readahead with no limit on the number of buffered
+    /// items.
+    ///
+    /// The worker never blocks waiting for the consumer: it runs through the
+    /// inner iterator as fast as it can, buffering everything the consumer
+    /// hasn't yet taken.
+    ///
+    /// **Caution:** Memory use is limited only by the length of the inner
+    /// iterator. Use this only for sources that are known to be finite and
+    /// reasonably small.
+    pub fn unbounded() -> Self {
+        ReadaheadBuilder {
+            buffer_size: None,
+            ..ReadaheadBuilder::new(0)
+        }
+    }
+
+    /// Set the name of the worker thread.
+    ///
+    /// By default the thread is named `readahead_iterator`.
+    pub fn name<S: Into<String>>(mut self, name: S) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Set the stack size of the worker thread, in bytes.
+    ///
+    /// By default this is the standard library's default for new threads.
+    pub fn stack_size(mut self, stack_size: usize) -> Self {
+        self.stack_size = Some(stack_size);
+        self
+    }
+
+    /// Give the worker thread a small stack, of 64 KiB.
+    ///
+    /// The default stack is several megabytes. That's only reserved address
+    /// space until it's touched, but with thousands of workers it adds up,
+    /// and on 32-bit targets address space is scarce. Simple inner iterators,
+    /// such as maps over ranges or reading from a channel, fit comfortably in
+    /// a small stack. Deep recursion or large arrays on the stack in the inner
+    /// iterator will overflow it, which aborts the process.
+    ///
+    /// This is the same as [`stack_size`](ReadaheadBuilder::stack_size) with
+    /// 64 KiB; the operating system may round it up.
+    pub fn small_stack(self) -> Self {
+        self.stack_size(SMALL_STACK_SIZE)
+    }
+
+    /// Observe panics from the inner iterator, and then end the stream cleanly.
+    ///
+    /// If the inner iterator panics, `handler` is called on the worker thread
+    /// with the panic payload, and the consumer sees the end of the stream
+    /// after any items produced before the panic.
+    ///
+    /// Setting a handler implies [`PanicPolicy::Swallow`], unless another
+    /// policy is set with [`panic_policy`](ReadaheadBuilder::panic_policy).
+    /// The handler is called before the policy is applied. Since it consumes
+    /// the payload, with [`PanicPolicy::Propagate`] the consumer then panics
+    /// with a generic message.
+    ///
+    /// ```
+    /// use std::sync::{Arc, Mutex};
+    /// use readahead_iterator::ReadaheadBuilder;
+    ///
+    /// let panicked = Arc::new(Mutex::new(false));
+    /// let panicked_clone = panicked.clone();
+    /// let items: Vec<u32> = ReadaheadBuilder::new(4)
+    ///     .on_panic(move |_payload| *panicked_clone.lock().unwrap() = true)
+    ///     .spawn((0..5).map(|i| if i < 3 { i } else { panic!("oops") }))
+    ///     .unwrap()
+    ///     .collect();
+    /// assert_eq!(items, [0, 1, 2]);
+    /// assert!(*panicked.lock().unwrap());
+    /// ```
+    pub fn on_panic<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(Box<dyn Any + Send>) + Send + 'static,
+    {
+        self.on_panic = Some(Box::new(handler));
+        self
+    }
+
+    /// Call `handler` on the worker thread once the inner iterator is
+    /// exhausted, just before the end of the stream is sent.
+    ///
+    /// This signals that the source ended naturally, for example to flush a
+    /// log or start follow-up work, without polling. It's called at most once,
+    /// and not at all if the consumer is dropped before the worker reaches the
+    /// end, or if the inner iterator panics. The consumer sees the end of the
+    /// stream only after the handler returns.
+    ///
+    /// ```
+    /// use std::sync::mpsc::channel;
+    /// use readahead_iterator::ReadaheadBuilder;
+    ///
+    /// let (done_tx, done_rx) = channel();
+    /// let rah = ReadaheadBuilder::new(4)
+    ///     .on_complete(move || done_tx.send(()).unwrap())
+    ///     .spawn(0..3)
+    ///     .unwrap();
+    /// done_rx.recv().unwrap();
+    /// assert_eq!(rah.count(), 3);
+    /// ```
+    pub fn on_complete<F>(mut self, handler: F) -> Self
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.on_complete = Some(Box::new(handler));
+        self
+    }
+
+    /// Choose what happens if the inner iterator panics.
+    ///
+    /// The default is [`PanicPolicy::Propagate`], unless a handler is set with
+    /// [`on_panic`](ReadaheadBuilder::on_panic), in which case it's
+    /// [`PanicPolicy::Swallow`].
+    ///
+    /// ```
+    /// use readahead_iterator::{PanicPolicy, ReadaheadBuilder};
+    ///
+    /// let items: Vec<u32> = ReadaheadBuilder::new(4)
+    ///     .panic_policy(PanicPolicy::Swallow)
+    ///     .spawn((0..5).map(|i| if i < 3 { i } else { panic!("oops") }))
+    ///     .unwrap()
+    ///     .collect();
+    /// assert_eq!(items, [0, 1, 2]);
+    /// ```
+    pub fn panic_policy(mut self, policy: PanicPolicy) -> Self {
+        self.panic_policy = Some(policy);
+        self
+    }
+
+    /// Wait for the worker thread to finish when the `Readahead` is dropped.
+    ///
+    /// By default, dropping a `Readahead` doesn't wait: the worker notices
+    /// that the consumer is gone the next time it tries to send an item, and
+    /// then stops, but it may still be running for some time afterwards, and
+    /// holding any resources owned by the inner iterator.
+    ///
+    /// With this option, `drop` hangs up on the worker, and then waits until
+    /// it finishes and joins the thread. This waits up to `timeout`, or
+    /// indefinitely if `timeout` is `None`.
+    ///
+    /// A worker blocked inside the inner iterator's `next()`, for example
+    /// reading from a network connection, can't be interrupted, and can take
+    /// longer than the timeout to notice that it should stop. In that case
+    /// `drop` returns after the timeout and the thread is detached: it stops
+    /// when that call to `next()` returns.
+    pub fn join_on_drop(mut self, timeout: Option<Duration>) -> Self {
+        self.join_on_drop = Some(timeout);
+        self
+    }
+
+    /// Call `on_degenerate` if readahead doesn't seem to be helping.
+    ///
+    /// If the buffer is empty on nearly every call to `next()` over the first
+    /// hundred items, the consumer is almost always waiting for the producer,
+    /// and `on_degenerate` is called once, on the consumer thread, with a
+    /// message suggesting a look at the buffer size. It decides what to do
+    /// with it, such as logging it.
+    ///
+    /// This is a development aid, and is off by default.
+    ///
+    /// ```
+    /// use readahead_iterator::ReadaheadBuilder;
+    ///
+    /// let rah = ReadaheadBuilder::new(4)
+    ///     .on_degenerate(|warning| eprintln!("{}", warning))
+    ///     .spawn(0..1000)
+    ///     .unwrap();
+    /// assert_eq!(rah.count(), 1000);
+    /// ```
+    pub fn on_degenerate<F>(mut self, on_degenerate: F) -> Self
+    where
+        F: Fn(&str) + Send + 'static,
+    {
+        self.on_degenerate = Some(Box::new(on_degenerate));
+        self
+    }
+
+    /// When the `Readahead` is dropped, wait up to `timeout` for the worker to
+    /// finish the item it's producing.
+    ///
+    /// This gives an item with side effects, such as writing a temporary file,
+    /// a bounded time to reach a clean stopping point, rather than being
+    /// abandoned partway through. `drop` hangs up on the worker, so it won't
+    /// start another item, and returns as soon as the current call to the
+    /// inner iterator's `next()` returns, or when the worker is done. Unlike
+    /// [`join_on_drop`](ReadaheadBuilder::join_on_drop), it doesn't wait for
+    /// the inner iterator to be dropped or the thread to exit.
+    ///
+    /// If `next()` blocks for longer than `timeout`, `drop` returns anyway,
+    /// and the worker stops when `next()` eventually returns.
+    pub fn linger(mut self, timeout: Duration) -> Self {
+        self.linger = Some(timeout);
+        self
+    }
+
+    /// Call `interrupt` when the `Readahead` is dropped before the worker is
+    /// done, to make a blocked inner iterator return.
+    ///
+    /// A worker blocked inside the inner iterator's `next()`, for example on a
+    /// pipe that never closes, can't notice that the consumer has gone, and
+    /// no thread can be stopped from the outside without its cooperation. So
+    /// without help such a worker stays blocked forever, holding everything
+    /// the inner iterator owns.
+    ///
+    /// `interrupt` is that help: it should do whatever makes `next()` return,
+    /// such as closing a file descriptor, shutting down a socket, or setting a
+    /// flag the source checks. Once it returns, the worker stops as usual. The
+    /// callback runs on the thread that drops the `Readahead`, after hanging up
+    /// and before waiting for the worker with
+    /// [`linger`](ReadaheadBuilder::linger) or
+    /// [`join_on_drop`](ReadaheadBuilder::join_on_drop).
+    ///
+    /// ```
+    /// use std::iter;
+    /// use std::sync::mpsc::channel;
+    /// use readahead_iterator::ReadaheadBuilder;
+    ///
+    /// let (sender, receiver) = channel::<u32>();
+    /// let rah = ReadaheadBuilder::new(4)
+    ///     .join_on_drop(None)
+    ///     .interruptible_with(move || drop(sender))
+    ///     .spawn(iter::from_fn(move || receiver.recv().ok()))
+    ///     .unwrap();
+    /// // The worker is blocked waiting for input that will never come, until
+    /// // dropping the sender interrupts it.
+    /// drop(rah);
+    /// ```
+    pub fn interruptible_with<F>(mut self, interrupt: F) -> Self
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.interrupt = Some(Box::new(interrupt));
+        self
+    }
+
+    /// When the `Readahead` is dropped, drop any buffered items one at a time,
+    /// in source order, on the dropping thread.
+    ///
+    /// This is useful for items whose destructors have ordering-sensitive side
+    /// effects. Items still held by the worker are dropped later, on the worker
+    /// thread: see [Dropping](Readahead#dropping).
+    pub fn drop_in_order(mut self, drop_in_order: bool) -> Self {
+        self.drop_in_order = drop_in_order;
+        self
+    }
+
+    /// When the buffer is full, combine new items with the most recently
+    /// produced one using `f`, rather than waiting for the consumer.
+    ///
+    /// With this option the worker never blocks on a full buffer. Instead it
+    /// keeps pulling from the inner iterator, folding each new item into the
+    /// latest unsent one with `f(older, newer)`, so memory use stays bounded
+    /// while no information is lost, as long as items can be meaningfully
+    /// combined: counters, deltas, or "latest snapshot wins" state.
+    ///
+    /// This changes the number of items the consumer sees, and only makes
+    /// sense for such reducible items. Since the worker doesn't wait, a fast
+    /// inner iterator will keep a CPU busy while the consumer lags.
+    ///
+    /// This has no effect on an [unbounded](ReadaheadBuilder::unbounded)
+    /// buffer, which is never full.
+    ///
+    /// ```
+    /// use std::thread::sleep;
+    /// use std::time::Duration;
+    /// use readahead_iterator::ReadaheadBuilder;
+    ///
+    /// let rah = ReadaheadBuilder::new(1)
+    ///     .coalesce(|a, b| a + b)
+    ///     .spawn(1..=100)
+    ///     .unwrap();
+    /// sleep(Duration::from_millis(100));
+    /// let counts: Vec<u32> = rah.collect();
+    /// assert!(counts.len() < 100);
+    /// assert_eq!(counts.iter().sum::<u32>(), 5050);
+    /// ```
+    pub fn coalesce<F>(mut self, f: F) -> Self
+    where
+        F: Fn(T, T) -> T + Send + 'static,
+    {
+        self.coalesce = Some(Box::new(f));
+        self
+    }
+
+    /// Choose whether buffered items are delivered oldest or newest first.
+    ///
+    /// The default, [`DeliveryOrder::Fifo`], preserves the order of the
+    /// source. With [`DeliveryOrder::Lifo`], a consumer that has fallen behind
+    /// gets the freshest item first, as for "latest reading wins" live data.
+    ///
+    /// **Caution:** LIFO delivery gives up the ordering guarantee: items come
+    /// in neither source order nor any other predictable order, since it
+    /// depends on how far ahead the worker has got each time.
+    ///
+    /// The buffer still holds at most `buffer_size` items. This has no effect
+    /// with [`coalesce`](ReadaheadBuilder::coalesce), and
+    /// [`Readahead::position`] counts items delivered, not a point in the
+    /// source.
+    ///
+    /// ```
+    /// use readahead_iterator::{DeliveryOrder, ReadaheadBuilder};
+    ///
+    /// let mut rah = ReadaheadBuilder::new(4)
+    ///     .order(DeliveryOrder::Lifo)
+    ///     .spawn(0..4)
+    ///     .unwrap();
+    /// rah.warm_up(4);
+    /// assert_eq!(rah.next(), Some(3));
+    /// ```
+    pub fn order(mut self, order: DeliveryOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Don't spawn the worker thread until the first call to `next()`.
+    ///
+    /// This is useful when building many readahead iterators that might not
+    /// all be consumed: if the `Readahead` is dropped without ever being
+    /// polled, no thread is created, and the inner iterator is dropped on the
+    /// thread that drops the `Readahead`.
+    ///
+    /// Since nothing is read ahead until the first call to `next()`, that call
+    /// waits for the first item to be produced, and the following ones overlap
+    /// with the consumer as usual. [`Readahead::warm_up`] returns immediately
+    /// if the worker hasn't started.
+    ///
+    /// [`spawn`](ReadaheadBuilder::spawn) can't report a failure to spawn the
+    /// thread in this mode, so instead the first call to `next()` panics.
+    ///
+    /// ```
+    /// use readahead_iterator::ReadaheadBuilder;
+    ///
+    /// let mut rah = ReadaheadBuilder::new(4).lazy(true).spawn(0..10).unwrap();
+    /// // No thread is running yet.
+    /// assert_eq!(rah.next(), Some(0));
+    /// ```
+    pub fn lazy(mut self, lazy: bool) -> Self {
+        self.lazy = lazy;
+        self
+    }
+
+    /// Check that items arrive in the order they were produced.
+    ///
+    /// The worker sends the position of each item in the source alongside it,
+    /// and the consumer panics if an item arrives out of order. This is a
+    /// self-test aid for debugging pipelines, and costs an extra channel
+    /// operation per item, so it's off by default.
+    pub fn verify_ordering(mut self, verify: bool) -> Self {
+        self.verify_ordering = verify;
+        self
+    }
+
+    /// Limit the total size of buffered items to `max` bytes, as measured by
+    /// `size_fn`.
+    ///
+    /// For items of widely varying size, such as the contents of files, a
+    /// limit on the number of items doesn't bound memory use well. With this
+    /// option the worker waits, before sending an item, until it fits in the
+    /// remaining byte budget. An item bigger than `max` by itself is sent
+    /// once the buffer is empty.
+    ///
+    /// This applies in addition to the item limit given to
+    /// [`new`](ReadaheadBuilder::new): use
+    /// [`unbounded`](ReadaheadBuilder::unbounded) to limit only the number of
+    /// bytes. `size_fn` is called on the worker thread. The limit is not
+    /// applied when [coalescing](ReadaheadBuilder::coalesce), since that
+    /// never waits for the consumer.
+    ///
+    /// ```
+    /// use readahead_iterator::ReadaheadBuilder;
+    ///
+    /// let total: usize = ReadaheadBuilder::unbounded()
+    ///     .buffer_bytes(1 << 20, |buf: &Vec<u8>| buf.len())
+    ///     .spawn((0..10).map(|i| vec![0u8; i * 1000]))
+    ///     .unwrap()
+    ///     .map(|buf| buf.len())
+    ///     .sum();
+    /// assert_eq!(total, 45_000);
+    /// ```
+    pub fn buffer_bytes<F>(mut self, max: usize, size_fn: F) -> Self
+    where
+        F: Fn(&T) -> usize + Send + 'static,
+    {
+        self.byte_limit = Some((max, Box::new(size_fn)));
+        self
+    }
+
+    /// When the buffer is full, retry sending up to `iters` times, spinning,
+    /// before blocking.
+    ///
+    /// Blocking on a full buffer parks the worker thread, and the consumer has
+    /// to wake it again. When the consumer is nearly always just about to take
+    /// the next item, spinning briefly can hand items over with lower latency
+    /// and less thrashing, at the cost of some CPU time.
+    ///
+    /// It hurts when the consumer is slow, idle, or bursty: the spinning
+    /// burns CPU, which may be needed by the consumer itself, without avoiding
+    /// the block. On a single core it can only hurt, since the consumer can't
+    /// make progress while the worker spins. Measure before using it: the
+    /// `spin` example times a tight handoff with several settings.
+    ///
+    /// The default is zero, to block straight away.
+    pub fn spin_before_block(mut self, iters: usize) -> Self {
+        self.spin_before_block = iters;
+        self
+    }
+
+    /// Slow the worker down while the consumer keeps falling behind.
+    ///
+    /// When the buffer has been full on several sends in a row, readahead is
+    /// already as far ahead as it can get, and the worker spends its time
+    /// being woken to send one more item each time the consumer takes one.
+    /// With this on, the worker then sleeps for a millisecond after each send,
+    /// so that it's woken less often and fills several slots at a time,
+    /// until a send finds room in the buffer again.
+    ///
+    /// This saves CPU time and context switches on the worker when the
+    /// consumer is the bottleneck, at the cost of letting the buffer run a
+    /// little below full. It makes no difference to an unbounded buffer,
+    /// which is never full.
+    ///
+    /// It's off by default.
+    pub fn throttle_on_lag(mut self, throttle_on_lag: bool) -> Self {
+        self.throttle_on_lag = throttle_on_lag;
+        self
+    }
+
+    /// When the buffer is empty, have `next()` wait until at least `n` items
+    /// are buffered, or the stream ends, rather than waking for each item.
+    ///
+    /// Each handoff between the threads through an empty buffer costs the
+    /// consumer a wakeup. With small, cheap items this signalling can cost
+    /// more than the items themselves. Waiting for a batch spreads one wakeup
+    /// over `n` items, which are then delivered straight from the buffer, at
+    /// the cost of latency: the first item of each batch waits for the last.
+    ///
+    /// `n` is capped at the buffer size, and with an
+    /// [adaptive buffer](ReadaheadBuilder::adaptive_buffer), at its current
+    /// size. This has no effect with a buffer size of zero, which already
+    /// hands over one item at a time, or with
+    /// [`buffer_bytes`](ReadaheadBuilder::buffer_bytes), which might stop the
+    /// batch ever filling. The `min_batch` example compares several sizes.
+    ///
+    /// ```
+    /// use readahead_iterator::ReadaheadBuilder;
+    ///
+    /// let rah = ReadaheadBuilder::new(64)
+    ///     .min_deliver_batch(16)
+    ///     .spawn(0..100)
+    ///     .unwrap();
+    /// assert_eq!(rah.count(), 100);
+    /// ```
+    pub fn min_deliver_batch(mut self, n: usize) -> Self {
+        self.min_batch = Some(n);
+        self
+    }
+
+    /// Start with a buffer of `initial` items, and let it grow up to `max`
+    /// items when the consumer keeps finding it empty.
+    ///
+    /// This replaces the buffer size given to
+    /// [`new`](ReadaheadBuilder::new). A small buffer warms up quickly, and
+    /// holds little memory, but an empty buffer means the consumer is waiting
+    /// for the worker, and a bigger one may let the worker get far enough
+    /// ahead to cover bursts. So each time `next()` finds the buffer empty,
+    /// after the first item and while the worker is still running, the
+    /// buffer's size doubles, up to `max`. It never shrinks.
+    ///
+    /// `initial` is at least one, and at most `max`. The channel is allocated
+    /// for `max` items, but only holds as many as the current size. This has
+    /// no effect with [`coalesce`](ReadaheadBuilder::coalesce), which
+    /// depends on the buffer being full.
+    ///
+    /// ```
+    /// use readahead_iterator::ReadaheadBuilder;
+    ///
+    /// let rah = ReadaheadBuilder::new(0)
+    ///     .adaptive_buffer(2, 64)
+    ///     .spawn(0..100)
+    ///     .unwrap();
+    /// assert_eq!(rah.count(), 100);
+    /// ```
+    pub fn adaptive_buffer(mut self, initial: usize, max: usize) -> Self {
+        let max = max.max(1);
+        self.buffer_size = Some(max);
+        self.adaptive_buffer = Some((initial.clamp(1, max), max));
+        self
+    }
+
+    /// Limit the memory taken by buffered items to `bytes`, blocking the
+    /// worker when the buffer is full.
+    ///
+    /// Each item is charged its own size,
+    /// [`mem::size_of::<T>()`](std::mem::size_of), plus, if
+    /// [`buffer_bytes`](ReadaheadBuilder::buffer_bytes) is also set, the size
+    /// its `size_fn` measures, such as the heap memory of a `Vec`. Before
+    /// sending an item, the worker waits until it fits in what's left of
+    /// `bytes`; an item bigger than `bytes` by itself is sent once the buffer
+    /// is empty. If `buffer_bytes` is set, its own maximum applies to the same
+    /// combined count, so the lower of the two is the cap.
+    ///
+    /// This applies as well as the limit on the number of items given to
+    /// [`new`](ReadaheadBuilder::new), and the worker stops at whichever is
+    /// reached first. The buffer's channel allocates room for all its items
+    /// when it's created, so the number of items is also reduced, if need
+    /// be, to what fits in `bytes` by their own size, with room for at least
+    /// one: a huge `buffer_size` of large items doesn't then ask for more
+    /// memory than there is. An [unbounded](ReadaheadBuilder::unbounded)
+    /// buffer becomes bounded. Zero-sized items take no room.
+    ///
+    /// Like `buffer_bytes`, the limit is not applied when
+    /// [coalescing](ReadaheadBuilder::coalesce).
+    ///
+    /// ```
+    /// use readahead_iterator::ReadaheadBuilder;
+    ///
+    /// // A buffer of a thousand 1MB buffers could take 1GB, but this holds
+    /// // no more than four.
+    /// let rah = ReadaheadBuilder::new(1000)
+    ///     .buffer_bytes(usize::MAX, |buf: &Vec<u8>| buf.len())
+    ///     .max_memory(4 << 20)
+    ///     .spawn((0..20).map(|_| vec![0u8; 1 << 20]))
+    ///     .unwrap();
+    /// assert_eq!(rah.count(), 20);
+    /// ```
+    pub fn max_memory(mut self, bytes: usize) -> Self {
+        self.max_memory = Some(bytes);
+        self
+    }
+
+    /// Run parallel maps serially, in a fixed order, so that tests of code
+    /// that uses them are reproducible.
+    ///
+    /// This affects
+    /// [`spawn_map_parallel_chunks`](ReadaheadBuilder::spawn_map_parallel_chunks):
+    /// with it on, no threads are started, and each chunk is mapped in turn
+    /// on the consumer thread when it's needed, as with
+    /// [`enabled(false)`](ReadaheadBuilder::enabled). The results are the
+    /// same, since the parallel map preserves order; only the timing and
+    /// interleaving of calls to the mapping function change.
+    ///
+    /// It's off by default.
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+
+    /// Stop taking items from the inner iterator once `deadline` has passed.
+    ///
+    /// This is for time-boxed jobs, which should process as many items as
+    /// they can in a fixed time. The worker checks the time before each call
+    /// to the inner iterator's `next()`, so an item that's already being made
+    /// when the deadline passes is still finished and delivered, as are all
+    /// the items buffered before it. After that, the stream ends.
+    ///
+    /// Ending at the deadline doesn't count as the inner iterator being
+    /// exhausted: [`on_complete`](ReadaheadBuilder::on_complete) isn't
+    /// called, and [`Readahead::source_ended`] stays false.
+    ///
+    /// The time is read from the [`clock`](ReadaheadBuilder::clock).
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use readahead_iterator::{Clock, ManualClock, ReadaheadBuilder};
+    ///
+    /// let clock = ManualClock::new();
+    /// let deadline = clock.now() + Duration::from_secs(30);
+    /// let worker_clock = clock.clone();
+    /// let processed = ReadaheadBuilder::new(4)
+    ///     .clock(clock.clone())
+    ///     .deadline(deadline)
+    ///     .spawn((0..).map(move |i| {
+    ///         // Each item takes ten seconds to make.
+    ///         worker_clock.sleep(Duration::from_secs(10));
+    ///         i
+    ///     }))
+    ///     .unwrap()
+    ///     .count();
+    /// assert_eq!(processed, 3);
+    /// ```
+    pub fn deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Stop taking items from the inner iterator once `duration` has passed
+    /// since the readahead was spawned.
+    ///
+    /// This is the same as a [`deadline`](ReadaheadBuilder::deadline) of the
+    /// spawn time plus `duration`; if both are set, whichever is earlier
+    /// applies.
+    pub fn max_duration(mut self, duration: Duration) -> Self {
+        self.max_duration = Some(duration);
+        self
+    }
+
+    /// Call `on_slow` whenever the inner iterator takes longer than
+    /// `threshold` to produce a single item.
+    ///
+    /// This is a diagnostic aid for finding the outliers, such as one huge
+    /// file, that stall a pipeline, without instrumenting the source.
+    /// `on_slow` is given the item's position in the source, counting from
+    /// zero, and how long it took, and decides what to do about it, such as
+    /// logging a warning. It's called on the worker thread, after the item is
+    /// made and before it's sent. The time to produce an item is measured
+    /// around each call to the inner iterator's `next()`, so it doesn't
+    /// include time the worker spends waiting for room in the buffer.
+    ///
+    /// The time is read from the [`clock`](ReadaheadBuilder::clock). It's
+    /// off by default, and costs nothing then.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use readahead_iterator::ReadaheadBuilder;
+    ///
+    /// let sizes = ReadaheadBuilder::new(4)
+    ///     .warn_slow(Duration::from_secs(1), |index, elapsed| {
+    ///         eprintln!("item {} took {:?}", index, elapsed)
+    ///     })
+    ///     .spawn(vec![10, 20, 30].into_iter())
+    ///     .unwrap();
+    /// assert_eq!(sizes.sum::<u32>(), 60);
+    /// ```
+    pub fn warn_slow<F>(mut self, threshold: Duration, on_slow: F) -> Self
+    where
+        F: Fn(usize, Duration) + Send + 'static,
+    {
+        self.warn_slow = Some((threshold, Box::new(on_slow)));
+        self
+    }
+
+    /// Read the time from `clock`, rather than the system clock.
+    ///
+    /// This lets tests check time-dependent settings, such as a
+    /// [`deadline`](ReadaheadBuilder::deadline) or
+    /// [`warn_slow`](ReadaheadBuilder::warn_slow), with a
+    /// [`ManualClock`](crate::ManualClock) instead of real delays.
+    pub fn clock<C>(mut self, clock: C) -> Self
+    where
+        C: Clock + 'static,
+    {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Turn readahead on or off.
+    ///
+    /// When it's off, [`spawn`](ReadaheadBuilder::spawn) doesn't start a
+    /// thread: the `Readahead` calls the inner iterator directly on the
+    /// consumer thread, as if it weren't there, and the other options are
+    /// ignored. This is a debugging aid, for checking whether readahead is
+    /// involved in a problem without changing the types at call sites.
+    ///
+    /// This doesn't affect [`spawn_with`](ReadaheadBuilder::spawn_with), whose
+    /// iterator can only live on a worker thread.
+    ///
+    /// Readahead is on by default.
+    ///
+    /// ```
+    /// use std::thread;
+    /// use readahead_iterator::ReadaheadBuilder;
+    ///
+    /// let consumer = thread::current().id();
+    /// let on_consumer = ReadaheadBuilder::new(4)
+    ///     .enabled(false)
+    ///     .spawn((0..3).map(|_| thread::current().id()))
+    ///     .unwrap()
+    ///     .all(|id| id == consumer);
+    /// assert!(on_consumer);
+    /// ```
+    pub fn enabled(mut self, enabled: bool) -> Self {
+        self.enabled = enabled;
+        self
+    }
+
+    /// Spawn a worker thread that reads ahead from `inner`.
+    ///
+    /// Returns an error if the thread can't be spawned. With
+    /// [`lazy`](ReadaheadBuilder::lazy) the thread is spawned later, and this
+    /// always succeeds.
+    pub fn spawn<I>(self, inner: I) -> io::Result<Readahead<T>>
+    where
+        I: Iterator<Item = T> + Send + 'static,
+    {
+        if !self.enabled {
+            let (_, receiver) = sync_channel(0);
+            let mut readahead = Readahead::from_parts(receiver, None);
+            readahead.inline = Some(Box::new(inner));
+            return Ok(readahead);
+        }
+        self.spawn_with(move || inner)
+    }
+
+    /// Spawn a worker thread that calls `factory` to build the inner iterator,
+    /// and then reads ahead from it.
+    ///
+    /// This allows reading ahead from an iterator that isn't `Send`, such as
+    /// one holding an `Rc`. Only `factory` moves to the worker thread, so only
+    /// it needs to be `Send`. The iterator it returns is created, used and
+    /// dropped entirely on the worker thread, and so needn't be `Send`, but
+    /// the items it yields still cross to the consumer and must be.
+    ///
+    /// A panic inside `factory` is handled like a panic in the inner iterator.
+    ///
+    /// `factory` runs on the worker thread before the first item is
+    /// produced, so it's also the place for setup that affects the current
+    /// thread, such as pinning it to a CPU core with a crate like
+    /// `core_affinity`. This crate doesn't do that itself, since it would
+    /// need platform calls that aren't possible without `unsafe` code.
+    ///
+    /// Likewise, to attribute the worker's events to the `tracing` span that
+    /// was current when the readahead was created, capture the span on the
+    /// consumer, move it into `factory`, and have the iterator it returns
+    /// enter the span around each call to the inner `next()`, for example
+    /// with `iter::from_fn(move || span.in_scope(|| inner.next()))`.
+    ///
+    /// ```
+    /// use std::rc::Rc;
+    /// use readahead_iterator::ReadaheadBuilder;
+    ///
+    /// let lengths: Vec<usize> = ReadaheadBuilder::new(4)
+    ///     .spawn_with(|| {
+    ///         let words: Vec<Rc<str>> = vec!["one".into(), "three".into()];
+    ///         words.into_iter().map(|word| word.len())
+    ///     })
+    ///     .unwrap()
+    ///     .collect();
+    /// assert_eq!(lengths, [3, 5]);
+    /// ```
+    pub fn spawn_with<F, I>(self, factory: F) -> io::Result<Readahead<T>>
+    where
+        F: FnOnce() -> I + Send + 'static,
+        I: Iterator<Item = T>,
+    {
+        self.spawn_with_shared(move |_| factory())
+    }
+
+    /// Spawn a worker thread that takes ownership of `owner`, and reads ahead
+    /// from an iterator that borrows from it.
+    ///
+    /// This is for an iterator over data owned by something else, such as
+    /// the lines of a `String` or the records in a memory-mapped file, which
+    /// can't be moved to the worker on its own because it borrows from the
+    /// owner. Instead the owner moves to the worker thread, and `make_iter`
+    /// is called there with a reference to it, to build the iterator. The
+    /// borrow never crosses between threads, and the owner outlives the
+    /// iterator: it's dropped on the worker just after the iterator.
+    ///
+    /// `owner` must be `Send + 'static`, since it moves to the worker, as
+    /// must `make_iter`. The iterator it returns is boxed, so that it can
+    /// borrow from the owner for however long the worker keeps it, and
+    /// needn't be `Send`; but its items cross to the consumer, so they must
+    /// be `Send + 'static`, and mustn't borrow from the owner themselves.
+    ///
+    /// Like [`spawn_with`](ReadaheadBuilder::spawn_with), this isn't affected
+    /// by [`enabled`](ReadaheadBuilder::enabled).
+    ///
+    /// ```
+    /// use readahead_iterator::ReadaheadBuilder;
+    ///
+    /// let text = String::from("one\nthree\nfive");
+    /// let lengths: Vec<usize> = ReadaheadBuilder::new(4)
+    ///     .spawn_owning(text, |text| Box::new(text.lines().map(str::len)))
+    ///     .unwrap()
+    ///     .collect();
+    /// assert_eq!(lengths, [3, 5, 4]);
+    /// ```
+    pub fn spawn_owning<O, F>(self, owner: O, make_iter: F) -> io::Result<Readahead<T>>
+    where
+        O: Send + 'static,
+        F: for<'a> FnOnce(&'a O) -> Box<dyn Iterator<Item = T> + 'a> + Send + 'static,
+    {
+        self.spawn_running(move |worker, _| {
+            // The owner is declared first, so that it's dropped after the
+            // iterator that borrows from it.
+            let owner = owner;
+            worker.run(|| make_iter(&owner));
+        })
+    }
+
+    /// Deliver the greatest buffered item first, rather than the oldest.
+    pub(crate) fn priority(mut self) -> Self
+    where
+        T: Ord,
+    {
+        self.priority = Some(Box::new(BinaryHeap::new()));
+        self
+    }
+
+    /// Spawn the worker with `thread_builder`, exactly as configured.
+    pub(crate) fn thread_builder(mut self, thread_builder: thread::Builder) -> Self {
+        self.thread_builder = Some(thread_builder);
+        self
+    }
+
+    /// Like [`ReadaheadBuilder::spawn_with`], but the factory can see the
+    /// state shared with the consumer.
+    pub(crate) fn spawn_with_shared<F, I>(self, factory: F) -> io::Result<Readahead<T>>
+    where
+        F: FnOnce(&Arc<Shared>) -> I + Send + 'static,
+        I: Iterator<Item = T>,
+    {
+        self.spawn_running(move |worker, shared| worker.run(move || factory(&shared)))
+    }
+
+    /// Spawn a worker thread that calls `run` with the configured worker, and
+    /// the state it shares with the consumer.
+    fn spawn_running<R>(self, run: R) -> io::Result<Readahead<T>>
+    where
+        R: FnOnce(Worker<T>, Arc<Shared>) + Send + 'static,
+    {
+        let buffer_size = match self.max_memory {
+            Some(bytes) => {
+                // Zero-sized items take no room, however many there are.
+                let fit = bytes
+                    .checked_div(mem::size_of::<T>())
+                    .unwrap_or(usize::MAX)
+                    .max(1);
+                Some(self.buffer_size.map_or(fit, |size| size.min(fit)))
+            }
+            None => self.buffer_size,
+        };
+        let (sender, receiver) = match buffer_size {
+            Some(buffer_size) => {
+                let (sender, receiver) = sync_channel(buffer_size);
+                (WorkerSender::Bounded(sender), receiver)
+            }
+            None => {
+                let (sender, receiver) = channel();
+                (WorkerSender::Unbounded(sender), receiver)
+            }
+        };
+        let thread_builder = match self.thread_builder {
+            Some(thread_builder) => thread_builder,
+            None => {
+                let mut thread_builder = thread::Builder::new()
+                    .name(self.name.unwrap_or_else(|| DEFAULT_THREAD_NAME.to_owned()));
+                if let Some(stack_size) = self.stack_size {
+                    thread_builder = thread_builder.stack_size(stack_size);
+                }
+                thread_builder
+            }
+        };
+        let shared = Arc::new(Shared::default());
+        let byte_limit = match (self.max_memory, self.byte_limit) {
+            (None, byte_limit) => byte_limit,
+            (Some(bytes), None) => {
+                Some((bytes, Box::new(|_: &T| mem::size_of::<T>()) as SizeFn<T>))
+            }
+            (Some(bytes), Some((max, size_fn))) => Some((
+                bytes.min(max),
+                Box::new(move |item: &T| mem::size_of::<T>().saturating_add(size_fn(item)))
+                    as SizeFn<T>,
+            )),
+        };
+        let bytes_limited = byte_limit.is_some() && self.coalesce.is_none();
+        let (sequence, ordering_check) = if self.verify_ordering {
+            let (sender, receiver) = channel();
+            (Some(sender), Some(OrderingCheck::new(receiver)))
+        } else {
+            (None, None)
+        };
+        let held: Option<Box<dyn Held<T>>> = match self.priority {
+            _ if self.coalesce.is_some() => None,
+            Some(priority) => Some(priority),
+            None if self.order == DeliveryOrder::Lifo => Some(Box::new(Vec::new())),
+            None => None,
+        };
+        let adaptive_buffer = if self.coalesce.is_none() {
+            self.adaptive_buffer
+        } else {
+            None
+        };
+        // The buffer may have been made smaller to fit in memory.
+        let adaptive_buffer = adaptive_buffer.map(|(initial, max)| {
+            let max = buffer_size.map_or(max, |size| max.min(size));
+            (initial.min(max), max)
+        });
+        if let Some((initial, _)) = adaptive_buffer {
+            shared.set_limit(initial);
+        }
+        let clock = self.clock;
+        let deadline = self
+            .max_duration
+            .map(|duration| clock.now() + duration)
+            .into_iter()
+            .chain(self.deadline)
+            .min();
+        let worker = Worker {
+            sender,
+            shared: shared.clone(),
+            panic_policy: self.panic_policy.unwrap_or(if self.on_panic.is_some() {
+                PanicPolicy::Swallow
+            } else {
+                PanicPolicy::Propagate
+            }),
+            on_panic: self.on_panic,
+            on_complete: self.on_complete,
+            coalesce: self.coalesce,
+            sequence,
+            byte_limit,
+            spin_before_block: self.spin_before_block,
+            throttle_on_lag: self.throttle_on_lag,
+            adaptive: adaptive_buffer.is_some(),
+            deadline,
+            warn_slow: self.warn_slow,
+            clock,
+            // A rendezvous channel still passes one item at a time.
+            hold_limit: buffer_size.filter(|_| held.is_some()).map(|n| n.max(1)),
+        };
+        let worker_shared = shared.clone();
+        let start = move || thread_builder.spawn(move || run(worker, worker_shared));
+        let mut readahead = Readahead::from_parts(receiver, Some(shared.clone()));
+        let handle = if self.lazy {
+            readahead.start = Some(Box::new(start));
+            None
+        } else {
+            Some(start()?)
+        };
+        readahead.join_on_drop = self.join_on_drop.map(|timeout| JoinOnDrop {
+            handle,
+            shared,
+            timeout,
+        });
+        readahead.drop_in_order = self.drop_in_order;
+        readahead.buffer_size = buffer_size;
+        readahead.ordering_check = ordering_check;
+        readahead.linger = self.linger;
+        readahead.bytes_limited = bytes_limited;
+        readahead.interrupt = self.interrupt;
+        readahead.grow_to = adaptive_buffer.map(|(_, max)| max);
+        readahead.held = held;
+        // A rendezvous channel passes one item at a time anyway, and the
+        // worker counts the item as buffered while it's blocked handing it
+        // over, so the consumer's wait would never be woken.
+        if !bytes_limited && buffer_size != Some(0) {
+            readahead.min_batch = self
+                .min_batch
+                .map(|n| buffer_size.map_or(n, |size| n.min(size)));
+        }
+        readahead.degenerate_check = self
+            .on_degenerate
+            .map(|on_degenerate| DegenerateCheck::new(buffer_size, on_degenerate));
+        readahead.before_recv = readahead.start.is_some()
+            || readahead.degenerate_check.is_some()
+            || readahead.grow_to.is_some()
+            || readahead.min_batch.is_some();
+        Ok(readahead)
+    }
+}
+
+impl<U> ReadaheadBuilder<Vec<U>>
+where
+    U: Send + 'static,
+{
+    /// Split the items of `inner` into chunks of `chunk_size`, and map each
+    /// chunk through `f` on a pool of `workers` threads, delivering the mapped
+    /// chunks in order.
+    ///
+    /// See
+    /// [`IntoReadahead::readahead_map_parallel_chunks`](crate::IntoReadahead::readahead_map_parallel_chunks),
+    /// which this configures. The options of this builder apply to the
+    /// thread that puts the chunks back in order, and its buffer; see also
+    /// [`deterministic`](ReadaheadBuilder::deterministic).
+    ///
+    /// Returns an error if a thread can't be spawned.
+    ///
+    /// ```
+    /// use readahead_iterator::ReadaheadBuilder;
+    ///
+    /// let doubled: Vec<Vec<u32>> = ReadaheadBuilder::new(4)
+    ///     .deterministic(true)
+    ///     .spawn_map_parallel_chunks(0..5, 2, 4, |chunk| {
+    ///         chunk.iter().map(|i| i * 2).collect()
+    ///     })
+    ///     .unwrap()
+    ///     .collect();
+    /// assert_eq!(doubled, [vec![0, 2], vec![4, 6], vec![8]]);
+    /// ```
+    pub fn spawn_map_parallel_chunks<I, F>(
+        self,
+        inner: I,
+        chunk_size: usize,
+        workers: usize,
+        f: F,
+    ) -> io::Result<Readahead<Vec<U>>>
+    where
+        I: Iterator + Send + 'static,
+        I::Item: Send + 'static,
+        F: Fn(Vec<I::Item>) -> Vec<U> + Send + Sync + 'static,
+    {
+        assert!(workers > 0, "workers must be greater than zero");
+        if self.deterministic {
+            return self
+                .enabled(false)
+                .spawn(map_chunks_inline(inner, chunk_size, f));
+        }
+        let in_flight = self.buffer_size.map_or(usize::MAX, |buffer_size| {
+            workers.saturating_add(buffer_size)
+        });
+        map_parallel_chunks(inner, chunk_size, workers, f, in_flight, self)
+    }
+}
+
+impl<T> fmt::Debug for ReadaheadBuilder<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReadaheadBuilder")
+            .field("buffer_size", &self.buffer_size)
+            .field("name", &self.name)
+            .field("stack_size", &self.stack_size)
+            .field("on_panic", &self.on_panic.is_some())
+            .field("on_complete", &self.on_complete.is_some())
+            .field("panic_policy", &self.panic_policy)
+            .field("join_on_drop", &self.join_on_drop)
+            .field("on_degenerate", &self.on_degenerate.is_some())
+            .field("drop_in_order", &self.drop_in_order)
+            .field("coalesce", &self.coalesce.is_some())
+            .field("lazy", &self.lazy)
+            .field("verify_ordering", &self.verify_ordering)
+            .field("linger", &self.linger)
+            .field("enabled", &self.enabled)
+            .field("byte_limit", &self.byte_limit.as_ref().map(|(max, _)| max))
+            .field("interrupt", &self.interrupt.is_some())
+            .field("spin_before_block", &self.spin_before_block)
+            .field("throttle_on_lag", &self.throttle_on_lag)
+            .field("thread_builder", &self.thread_builder)
+            .field("order", &self.order)
+            .field("min_batch", &self.min_batch)
+            .field("adaptive_buffer", &self.adaptive_buffer)
+            .field("priority", &self.priority.is_some())
+            .field("max_memory", &self.max_memory)
+            .field("deterministic", &self.deterministic)
+            .field("deadline", &self.deadline)
+            .field("max_duration", &self.max_duration)
+            .field(
+                "warn_slow",
+                &self.warn_slow.as_ref().map(|(threshold, _)| threshold),
+            )
+            .finish()
+    }
+}
@@ -0,0 +1,116 @@
+// Copyright 2021 Martin Pool
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Reading ahead in fixed-size arrays.
+
+use std::convert::TryFrom;
+
+use crate::Readahead;
+
+/// What the worker sends for [`ReadaheadChunksExact`].
+pub(crate) enum Chunk<T, const N: usize> {
+    Full([T; N]),
+    /// The last few items, fewer than `N`, sent just before the end of the
+    /// stream.
+    Remainder(Vec<T>),
+}
+
+/// Groups items into arrays on the worker thread.
+pub(crate) struct ChunksExact<I: Iterator, const N: usize> {
+    inner: I,
+    done: bool,
+}
+
+impl<I: Iterator, const N: usize> ChunksExact<I, N> {
+    pub(crate) fn new(inner: I) -> Self {
+        assert!(N > 0, "chunk size must be non-zero");
+        ChunksExact { inner, done: false }
+    }
+}
+
+impl<I: Iterator, const N: usize> Iterator for ChunksExact<I, N> {
+    type Item = Chunk<I::Item, N>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let chunk: Vec<I::Item> = self.inner.by_ref().take(N).collect();
+        match <[I::Item; N]>::try_from(chunk) {
+            Ok(array) => Some(Chunk::Full(array)),
+            Err(remainder) => {
+                self.done = true;
+                Some(Chunk::Remainder(remainder))
+            }
+        }
+    }
+}
+
+/// A [`Readahead`] of fixed-size arrays of items.
+///
+/// Created by [`IntoReadahead::readahead_chunks_exact`](crate::IntoReadahead::readahead_chunks_exact).
+///
+/// If the number of items isn't a multiple of `N`, the last few are not
+/// returned by the iterator, but are available from
+/// [`remainder`](ReadaheadChunksExact::remainder) once the iterator has
+/// returned `None`.
+///
+/// ```
+/// use readahead_iterator::IntoReadahead;
+///
+/// let mut chunks = (0..7).readahead_chunks_exact::<3>(2);
+/// assert_eq!(chunks.next(), Some([0, 1, 2]));
+/// assert_eq!(chunks.next(), Some([3, 4, 5]));
+/// assert_eq!(chunks.next(), None);
+/// assert_eq!(chunks.remainder(), [6]);
+/// ```
+pub struct ReadaheadChunksExact<T: Send + 'static, const N: usize> {
+    inner: Readahead<Chunk<T, N>>,
+    remainder: Vec<T>,
+}
+
+impl<T, const N: usize> ReadaheadChunksExact<T, N>
+where
+    T: Send + 'static,
+{
+    pub(crate) fn new(inner: Readahead<Chunk<T, N>>) -> Self {
+        ReadaheadChunksExact {
+            inner,
+            remainder: Vec::new(),
+        }
+    }
+
+    /// The items left over at the end of the stream, fewer than `N`.
+    ///
+    /// This is empty until the iterator has returned `None`.
+    pub fn remainder(&self) -> &[T] {
+        &self.remainder
+    }
+
+    /// Take ownership of the items left over at the end of the stream.
+    pub fn into_remainder(self) -> Vec<T> {
+        self.remainder
+    }
+}
+
+impl<T, const N: usize> Iterator for ReadaheadChunksExact<T, N>
+where
+    T: Send + 'static,
+{
+    type Item = [T; N];
+
+    fn next(&mut self) -> Option<[T; N]> {
+        match self.inner.next()? {
+            Chunk::Full(array) => Some(array),
+            Chunk::Remainder(remainder) => {
+                self.remainder = remainder;
+                None
+            }
+        }
+    }
+}
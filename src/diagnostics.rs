@@ -0,0 +1,109 @@
+// Copyright 2021 Martin Pool
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Opt-in warnings about misconfiguration, and self-checks.
+
+use std::sync::mpsc::Receiver;
+
+use crate::builder::DegenerateHandler;
+
+/// Number of items observed before deciding whether readahead is helping.
+const DEGENERATE_SAMPLE: usize = 100;
+
+/// Percentage of `next()` calls finding an empty buffer that triggers a warning.
+const DEGENERATE_PERCENT: usize = 95;
+
+/// Rejects, at compile time, a buffer size `N` too small to overlap much work.
+pub(crate) struct MinBufferSize<const N: usize>;
+
+impl<const N: usize> MinBufferSize<N> {
+    /// Fails to compile, when used, unless `N` is at least 2.
+    pub(crate) const CHECK: () = assert!(N >= 2, "readahead buffer size must be at least 2");
+}
+
+/// Watches for a buffer that's empty on nearly every call to `next()`, meaning
+/// the consumer almost always waits for the producer and the two aren't
+/// overlapping.
+pub(crate) struct DegenerateCheck {
+    buffer_size: Option<usize>,
+    polls: usize,
+    empty: usize,
+    /// Told the warning, if there is one.
+    on_degenerate: DegenerateHandler,
+}
+
+impl DegenerateCheck {
+    pub(crate) fn new(buffer_size: Option<usize>, on_degenerate: DegenerateHandler) -> Self {
+        DegenerateCheck {
+            buffer_size,
+            polls: 0,
+            empty: 0,
+            on_degenerate,
+        }
+    }
+
+    /// Record whether the buffer was empty when `next()` was called.
+    ///
+    /// Once enough calls have been seen, passes the warning, if any, to the
+    /// handler, so the warning is given at most once.
+    pub(crate) fn observe(&mut self, buffer_was_empty: bool) {
+        if self.polls >= DEGENERATE_SAMPLE {
+            return;
+        }
+        self.polls += 1;
+        if buffer_was_empty {
+            self.empty += 1;
+        }
+        if self.polls == DEGENERATE_SAMPLE && self.empty * 100 >= DEGENERATE_PERCENT * self.polls {
+            (self.on_degenerate)(&format!(
+                "readahead_iterator: buffer was empty on {} of the first {} calls to next() \
+                 with buffer_size {:?}; readahead isn't overlapping much work. A larger \
+                 buffer may help if production is bursty; otherwise the producer is \
+                 the bottleneck.",
+                self.empty, self.polls, self.buffer_size
+            ));
+        }
+    }
+}
+
+/// Checks that items arrive in the order they were produced, using sequence
+/// numbers sent by the worker alongside each item.
+#[derive(Debug)]
+pub(crate) struct OrderingCheck {
+    /// The source position of each item, sent just after the item.
+    sequence: Receiver<usize>,
+    last: Option<usize>,
+}
+
+impl OrderingCheck {
+    pub(crate) fn new(sequence: Receiver<usize>) -> Self {
+        OrderingCheck {
+            sequence,
+            last: None,
+        }
+    }
+
+    /// Check the sequence number of an item that was just received.
+    ///
+    /// Panics if it's not later than the previous one.
+    pub(crate) fn observe(&mut self) {
+        let seq = self
+            .sequence
+            .recv()
+            .expect("readahead_iterator: no sequence number for received item");
+        if let Some(last) = self.last {
+            assert!(
+                seq > last,
+                "readahead_iterator: received item {} after item {}",
+                seq,
+                last
+            );
+        }
+        self.last = Some(seq);
+    }
+}
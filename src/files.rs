@@ -0,0 +1,72 @@
+// Copyright 2021 Martin Pool
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Reading whole files on a pool of threads.
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::parallel::map_parallel_chunks;
+use crate::ReadaheadBuilder;
+
+/// Read the contents of each of `paths` on a pool of `workers` threads,
+/// yielding them in the order of `paths`.
+///
+/// This is for processing many small files, where the time goes on opening
+/// and reading each one: several are read at once, overlapped with each
+/// other and with the consumer. Up to `workers + buffer_size` files are read
+/// ahead of the consumer.
+///
+/// A file that can't be read is returned as an `Err`, whose message includes
+/// the path, and the rest are still read. Each file is read whole into
+/// memory, so this isn't suitable for large files.
+///
+/// Panics if `workers` is zero.
+///
+/// ```
+/// use std::path::PathBuf;
+/// use readahead_iterator::readahead_files;
+///
+/// let paths = vec![PathBuf::from("Cargo.toml"), PathBuf::from("no-such-file")];
+/// let results: Vec<_> = readahead_files(paths.into_iter(), 2, 4).collect();
+/// let (path, contents) = results[0].as_ref().unwrap();
+/// assert_eq!(path, &PathBuf::from("Cargo.toml"));
+/// assert!(contents.starts_with(b"[package]"));
+/// assert!(results[1].is_err());
+/// ```
+pub fn readahead_files<I>(
+    paths: I,
+    workers: usize,
+    buffer_size: usize,
+) -> impl Iterator<Item = io::Result<(PathBuf, Vec<u8>)>>
+where
+    I: Iterator<Item = PathBuf> + Send + 'static,
+{
+    map_parallel_chunks(
+        paths,
+        1,
+        workers,
+        |paths: Vec<PathBuf>| paths.into_iter().map(read_file).collect(),
+        workers.saturating_add(buffer_size),
+        ReadaheadBuilder::new(buffer_size),
+    )
+    .expect("failed to spawn readahead_iterator thread")
+    .flatten()
+}
+
+/// Read a whole file, adding its path to any error.
+fn read_file(path: PathBuf) -> io::Result<(PathBuf, Vec<u8>)> {
+    match fs::read(&path) {
+        Ok(contents) => Ok((path, contents)),
+        Err(err) => Err(io::Error::new(
+            err.kind(),
+            format!("{}: {}", path.display(), err),
+        )),
+    }
+}
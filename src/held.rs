@@ -0,0 +1,41 @@
+// Copyright 2021 Martin Pool
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Holding received items to deliver them in some order other than FIFO.
+
+use std::collections::BinaryHeap;
+
+/// Items the consumer has taken from the channel but not yet delivered.
+pub(crate) trait Held<T>: Send {
+    fn hold(&mut self, item: T);
+
+    /// Take the item to be delivered next.
+    fn take_next(&mut self) -> Option<T>;
+}
+
+/// For LIFO delivery: the newest item first.
+impl<T: Send> Held<T> for Vec<T> {
+    fn hold(&mut self, item: T) {
+        self.push(item)
+    }
+
+    fn take_next(&mut self) -> Option<T> {
+        self.pop()
+    }
+}
+
+/// For priority delivery: the greatest item first.
+impl<T: Ord + Send> Held<T> for BinaryHeap<T> {
+    fn hold(&mut self, item: T) {
+        self.push(item)
+    }
+
+    fn take_next(&mut self) -> Option<T> {
+        self.pop()
+    }
+}
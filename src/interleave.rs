@@ -0,0 +1,72 @@
+// Copyright 2021 Martin Pool
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Taking items from several readaheads in turn.
+
+use std::collections::VecDeque;
+
+use crate::Readahead;
+
+/// Read ahead from each of `sources` on its own worker thread, and yield their
+/// items round-robin.
+///
+/// The order is strictly round-robin among the sources that haven't ended:
+/// one item from the first source, then one from the second, and so on,
+/// back to the first. When a source ends it's left out, and the rest carry
+/// on in the same order, so sources of different lengths are all read to
+/// the end. The iterator ends once every source has.
+///
+/// Each source has its own buffer of up to `buffer_size` items, so they all
+/// prefetch in parallel. But the strict order means that `next()` waits for
+/// the source whose turn it is, even if others have items ready.
+///
+/// ```
+/// use readahead_iterator::readahead_interleave;
+///
+/// let v: Vec<u32> = readahead_interleave(vec![0..3, 10..11, 20..22], 4).collect();
+/// assert_eq!(v, [0, 10, 20, 1, 21, 2]);
+/// ```
+pub fn readahead_interleave<I>(sources: Vec<I>, buffer_size: usize) -> ReadaheadInterleave<I::Item>
+where
+    I: Iterator + Send + 'static,
+    I::Item: Send + 'static,
+{
+    ReadaheadInterleave {
+        live: sources
+            .into_iter()
+            .map(|source| Readahead::new(source, buffer_size))
+            .collect(),
+    }
+}
+
+/// Yields items from several readaheads in turn.
+///
+/// Created by [`readahead_interleave`].
+#[derive(Debug)]
+pub struct ReadaheadInterleave<T: Send + 'static> {
+    /// The sources that haven't ended, starting with the one whose turn is
+    /// next.
+    live: VecDeque<Readahead<T>>,
+}
+
+impl<T> Iterator for ReadaheadInterleave<T>
+where
+    T: Send + 'static,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        while let Some(mut source) = self.live.pop_front() {
+            if let Some(item) = source.next() {
+                self.live.push_back(source);
+                return Some(item);
+            }
+        }
+        None
+    }
+}
@@ -55,13 +55,184 @@
 #![warn(missing_docs)]
 #![forbid(unsafe_code)]
 
-use std::sync::mpsc::{sync_channel, Receiver};
-use std::thread;
+use std::any::Any;
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::Hash;
+use std::io;
+use std::iter::{self, Chain};
+use std::mem;
+use std::ops::ControlFlow;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{sync_channel, Receiver, RecvTimeoutError, TryRecvError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+mod ack;
+mod adaptors;
+mod builder;
+mod chunks;
+mod clock;
+mod diagnostics;
+mod files;
+mod held;
+mod interleave;
+mod lines;
+mod parallel;
+mod partition;
+mod peekable;
+mod pipeline;
+mod pool;
+mod pooled;
+mod reader;
+#[cfg(feature = "registry")]
+mod registry;
+mod results;
+mod shared;
+mod shutdown;
+mod stats;
+mod summary;
+mod window;
+mod worker;
+mod zip;
+
+pub use crate::ack::Ack;
+use crate::ack::Acked;
+use crate::adaptors::{CoalesceTime, GroupBy, Sorted, Windows};
+pub use crate::builder::{DeliveryOrder, PanicPolicy, ReadaheadBuilder};
+use crate::builder::{Interrupt, DEFAULT_THREAD_NAME};
+use crate::chunks::ChunksExact;
+pub use crate::chunks::ReadaheadChunksExact;
+pub use crate::clock::{Clock, ManualClock, SystemClock};
+use crate::diagnostics::{DegenerateCheck, MinBufferSize, OrderingCheck};
+pub use crate::files::readahead_files;
+use crate::held::Held;
+pub use crate::interleave::{readahead_interleave, ReadaheadInterleave};
+pub use crate::lines::readahead_lines;
+pub use crate::partition::IntoReadaheadPairs;
+pub use crate::peekable::ReadaheadPeekable;
+use crate::pool::PoolTask;
+pub use crate::pool::ReadaheadPool;
+pub use crate::pooled::{readahead_pooled, Pooled};
+pub use crate::reader::{readahead_buffered_reader, ReadaheadReader};
+#[cfg(feature = "registry")]
+pub use crate::registry::ReadaheadRegistry;
+pub use crate::results::IntoReadaheadResults;
+use crate::shared::Shared;
+pub use crate::shutdown::ShutdownHandle;
+use crate::shutdown::UntilShutdown;
+pub use crate::stats::{DrainReport, ReadaheadStats};
+pub use crate::summary::ReadaheadWithSummary;
+use crate::summary::Summarize;
+pub use crate::window::ReadaheadWindow;
+pub use crate::zip::ReadaheadZip;
 
 /// An iterator adaptor that evaluates the iterator on a separate thread,
 /// and transports the items back to be consumed from the original thread.
+///
+/// # Deadlocks
+///
+/// The consumer waits for the worker whenever the buffer is empty, and the
+/// worker waits for the consumer whenever it's full. If the inner iterator
+/// itself waits for something the consumer does after calling `next()`, such
+/// as releasing a lock, or feeding a channel the inner iterator reads, the two
+/// threads deadlock. In general this can't be detected.
+///
+/// The one case that's certain to deadlock, the worker thread calling `next()`
+/// on its own `Readahead` with nothing buffered, panics instead in debug
+/// builds.
+///
+/// # Dropping
+///
+/// If a `Readahead` is dropped before the end of the stream, the worker stops
+/// the next time it tries to send an item. Unconsumed items are dropped as
+/// follows:
+///
+/// * Items waiting in the buffer are dropped on the thread that drops the
+///   `Readahead`. By default the order is up to the standard library's channel
+///   implementation, and is not guaranteed. With
+///   [`ReadaheadBuilder::drop_in_order`] they are dropped in source order.
+///
+/// * The item the worker was producing or trying to send when the consumer
+///   went away, and the inner iterator itself, are dropped on the worker
+///   thread. This can happen concurrently with, or after, dropping the
+///   buffered items, unless [`ReadaheadBuilder::join_on_drop`] is used to wait
+///   for the worker.
+///
+/// Since creating a `Readahead` starts a thread, discarding one straight away
+/// is almost certainly a mistake, and the compiler warns about it:
+///
+/// ```compile_fail
+/// #![deny(unused_must_use)]
+/// use readahead_iterator::IntoReadahead;
+///
+/// (0..10).readahead(4);
+/// ```
+///
+/// # Large items
+///
+/// Each item is moved into the channel by the worker and out of it again by
+/// `next()`, so an item that's large by value, such as a big array, is copied
+/// twice. The channel can't deliver into a caller's slot, so there's no way to
+/// avoid the second copy in this crate. Instead, send large items boxed: the
+/// allocation happens on the worker, and only the pointer is copied. The
+/// `large_items` example shows the difference for 16 KiB arrays.
+#[must_use = "a Readahead's worker thread stops when it's dropped"]
 pub struct Readahead<T: Send + 'static> {
-    receiver: Option<Receiver<Option<T>>>,
+    receiver: Receiver<Option<T>>,
+    /// True once the end of the stream has been received.
+    done: bool,
+    /// The number of items of the source that have been consumed, including
+    /// any skipped when resuming.
+    position: usize,
+    /// The number of items skipped when resuming.
+    skipped: usize,
+    /// State shared with the worker, if the worker was spawned by this crate.
+    shared: Option<Arc<Shared>>,
+    join_on_drop: Option<JoinOnDrop>,
+    degenerate_check: Option<DegenerateCheck>,
+    ordering_check: Option<OrderingCheck>,
+    /// Drop buffered items in order, on the consumer thread.
+    drop_in_order: bool,
+    /// How long to wait on drop for the worker to finish its current item.
+    linger: Option<Duration>,
+    /// True if the worker limits the size of buffered items.
+    bytes_limited: bool,
+    /// Called on drop to make a blocked inner iterator return.
+    interrupt: Option<Interrupt>,
+    /// The capacity of the buffer, if it's bounded and known.
+    buffer_size: Option<usize>,
+    /// Spawns the worker, if it's to be spawned lazily and hasn't been yet.
+    start: Option<StartWorker>,
+    /// The producer, if it runs on a [`ReadaheadPool`].
+    pool_task: Option<Arc<dyn PoolTask>>,
+    /// The inner iterator, if readahead is disabled and it's called directly.
+    inline: Option<Box<dyn Iterator<Item = T> + Send>>,
+    /// For LIFO or priority delivery, items received from the channel and
+    /// not yet delivered.
+    held: Option<Box<dyn Held<T>>>,
+    /// When the buffer is empty, wait for this many items before delivering
+    /// any.
+    min_batch: Option<usize>,
+    /// For an adaptive buffer, the most items it can grow to hold.
+    grow_to: Option<usize>,
+    /// True if `next()` may have something to do before receiving: starting
+    /// a lazy worker, watching for a degenerate buffer, growing an adaptive
+    /// one, or waiting for a batch. Without any of these, `next()` checks
+    /// only this.
+    before_recv: bool,
+}
+
+/// Spawns the worker thread.
+type StartWorker = Box<dyn FnOnce() -> io::Result<JoinHandle<()>> + Send>;
+
+/// How to wait for the worker when a [`Readahead`] is dropped.
+struct JoinOnDrop {
+    /// The worker thread, or `None` if it hasn't been started.
+    handle: Option<JoinHandle<()>>,
+    shared: Arc<Shared>,
+    timeout: Option<Duration>,
 }
 
 impl<T> Readahead<T>
@@ -75,6 +246,8 @@ where
     ///
     /// `buffer_size` is the maximum number of items that can be buffered.
     ///
+    /// Use [`ReadaheadBuilder`] to configure the worker thread.
+    ///
     /// ```
     /// use readahead_iterator::Readahead;
     /// let c = Readahead::new("Hello Ferris".chars(), 10)
@@ -82,79 +255,2534 @@ where
     ///     .count();
     /// # assert_eq!(c, 2);
     /// ```
+    #[must_use = "dropping the Readahead straight away stops its worker thread"]
     pub fn new<I>(inner: I, buffer_size: usize) -> Self
     where
         I: Iterator<Item = T> + Send + 'static,
     {
-        // TODO: What if the iterator is dropped?
-        let (sender, receiver) = sync_channel(buffer_size);
-        thread::Builder::new()
-            .name("readahead_iterator".to_owned())
-            .spawn(move || {
-                for item in inner {
-                    sender
-                        .send(Some(item))
-                        .expect("send from inner iterator failed");
-                }
-                sender.send(None).expect("send of final None failed");
-            })
-            .expect("failed to spawn readahead_iterator thread");
-        Readahead {
-            receiver: Some(receiver),
-        }
+        ReadaheadBuilder::new(buffer_size)
+            .spawn(inner)
+            .expect("failed to spawn readahead_iterator thread")
     }
-}
 
-impl<T> Iterator for Readahead<T>
-where
-    T: Send + 'static,
-{
-    type Item = T;
+    /// Apply a threaded readahead with a buffer size fixed at compile time.
+    ///
+    /// A buffer of 0 or 1 items overlaps little work, and is usually a
+    /// mistake, so `N` less than 2 is a compile error rather than a slow
+    /// program:
+    ///
+    /// ```compile_fail
+    /// use readahead_iterator::Readahead;
+    /// let r = Readahead::new_const::<1>(0..10);
+    /// ```
+    ///
+    /// Use [`Readahead::new`] for buffer sizes chosen at run time.
+    ///
+    /// ```
+    /// use readahead_iterator::Readahead;
+    /// let r = Readahead::new_const::<8>(0..10);
+    /// assert_eq!(r.count(), 10);
+    /// ```
+    #[must_use = "dropping the Readahead straight away stops its worker thread"]
+    pub fn new_const<const N: usize>(inner: impl Iterator<Item = T> + Send + 'static) -> Self {
+        let () = MinBufferSize::<N>::CHECK;
+        Readahead::new(inner, N)
+    }
 
-    fn next(&mut self) -> Option<T> {
-        let r = self
-            .receiver
-            .as_ref()
-            .and_then(|r| r.recv().expect("recv of iterator value failed"));
-        if r.is_none() {
-            self.receiver = None
-        }
-        r
+    /// Apply a threaded readahead to anything that can be turned into an
+    /// iterator, such as a `Vec`, calling `into_iter()` for you.
+    ///
+    /// The collection is consumed, so it and its items move to the worker
+    /// thread. This avoids the confusing lifetime errors from passing a
+    /// borrowing iterator, such as `vec.iter()`, to [`Readahead::new`].
+    ///
+    /// ```
+    /// use readahead_iterator::Readahead;
+    /// let names = vec!["a".to_owned(), "b".to_owned()];
+    /// let v: Vec<String> = Readahead::from_into_iter(names, 2).collect();
+    /// assert_eq!(v, ["a", "b"]);
+    /// ```
+    pub fn from_into_iter<C>(collection: C, buffer_size: usize) -> Self
+    where
+        C: IntoIterator<Item = T>,
+        C::IntoIter: Send + 'static,
+    {
+        Readahead::new(collection.into_iter(), buffer_size)
     }
-}
 
-/// Adds a `.readahead(buffer_size)` method to any iterator.
-///
-/// ```
-/// use readahead_iterator::IntoReadahead;
-///
-/// let c = "Some input data".chars()
-///     .readahead(10)
-///     .filter(|c| c.is_alphabetic())
-///     .count();
-/// # assert_eq!(c, 13);
-/// ```
-pub trait IntoReadahead<T>
-where
-    T: Send + 'static,
-{
-    /// Apply a readahead adaptor to an iterator.
+    /// Apply a threaded readahead, resuming after the first `skip` items.
     ///
-    /// `buffer_size` is the maximum number of buffered items.
-    fn readahead(self, buffer_size: usize) -> Readahead<T>
+    /// The skipped items are passed over on the worker thread, with
+    /// [`Iterator::nth`], before anything is sent, so skipping overlaps with
+    /// whatever the consumer does first.
+    ///
+    /// This pairs with [`position`](Readahead::position): a job that records
+    /// its position as a checkpoint can restart from there without processing
+    /// any item twice.
+    ///
+    /// ```
+    /// use readahead_iterator::Readahead;
+    /// let mut rah = Readahead::new_resume(0..10, 4, 3);
+    /// assert_eq!(rah.next(), Some(3));
+    /// assert_eq!(rah.position(), 4);
+    /// let mut rah = Readahead::new_resume(0..10, 4, rah.position());
+    /// assert_eq!(rah.next(), Some(4));
+    /// ```
+    pub fn new_resume<I>(inner: I, buffer_size: usize, skip: usize) -> Self
     where
-        Self: Send + 'static;
-}
+        I: Iterator<Item = T> + Send + 'static,
+    {
+        let mut readahead = Readahead::new(inner.skip(skip), buffer_size);
+        readahead.position = skip;
+        readahead.skipped = skip;
+        readahead
+    }
 
-impl<I, T> IntoReadahead<T> for I
-where
-    T: Send + 'static,
-    I: Iterator<Item = T>,
-{
-    fn readahead(self, buffer_size: usize) -> Readahead<T>
+    /// Apply a threaded readahead, naming the worker thread with `id`.
+    ///
+    /// The thread is named `readahead_iterator-{id}`, which helps tell apart
+    /// many threads spawned in a loop when debugging. For other names, use
+    /// [`ReadaheadBuilder::name`].
+    ///
+    /// ```
+    /// use readahead_iterator::Readahead;
+    /// let readaheads: Vec<Readahead<u32>> = (0..3)
+    ///     .map(|id| Readahead::new_with_id(0..10, 4, id))
+    ///     .collect();
+    /// # assert_eq!(readaheads.into_iter().flatten().count(), 30);
+    /// ```
+    pub fn new_with_id<I>(inner: I, buffer_size: usize, id: usize) -> Self
     where
-        Self: Send + 'static,
+        I: Iterator<Item = T> + Send + 'static,
     {
-        Readahead::new(self, buffer_size)
+        ReadaheadBuilder::new(buffer_size)
+            .name(format!("{}-{}", DEFAULT_THREAD_NAME, id))
+            .spawn(inner)
+            .expect("failed to spawn readahead_iterator thread")
+    }
+
+    /// Apply a threaded readahead, returning an error if the worker thread
+    /// can't be spawned.
+    ///
+    /// [`Readahead::new`] panics in that case.
+    #[must_use = "dropping the Readahead straight away stops its worker thread"]
+    pub fn try_new<I>(inner: I, buffer_size: usize) -> io::Result<Self>
+    where
+        I: Iterator<Item = T> + Send + 'static,
+    {
+        ReadaheadBuilder::new(buffer_size).spawn(inner)
+    }
+
+    /// Apply a threaded readahead, spawning the worker with a fully
+    /// configured [`thread::Builder`].
+    ///
+    /// This gives access to every option of the standard library's builder.
+    /// The builder is used as it is: if it has no name, the thread is left
+    /// unnamed, rather than given the default name.
+    ///
+    /// Returns an error if the thread can't be spawned.
+    ///
+    /// ```
+    /// use std::thread;
+    /// use readahead_iterator::Readahead;
+    ///
+    /// let builder = thread::Builder::new().name("prefetch".to_owned());
+    /// let r = Readahead::new_with_thread_builder(0..10, 4, builder).unwrap();
+    /// assert_eq!(r.sum::<u32>(), 45);
+    /// ```
+    pub fn new_with_thread_builder<I>(
+        inner: I,
+        buffer_size: usize,
+        thread_builder: thread::Builder,
+    ) -> io::Result<Self>
+    where
+        I: Iterator<Item = T> + Send + 'static,
+    {
+        ReadaheadBuilder::new(buffer_size)
+            .thread_builder(thread_builder)
+            .spawn(inner)
+    }
+
+    /// Apply a threaded readahead with no limit on the number of buffered
+    /// items.
+    ///
+    /// The worker runs as fast as it can without ever waiting for the
+    /// consumer.
+    ///
+    /// **Caution:** Every item the consumer hasn't yet taken is held in memory,
+    /// so this must only be used with iterators that are known to be finite and
+    /// reasonably small. See [`ReadaheadBuilder::unbounded`].
+    ///
+    /// ```
+    /// use readahead_iterator::Readahead;
+    /// let v: Vec<u32> = Readahead::new_unbounded(0..100).collect();
+    /// assert_eq!(v.len(), 100);
+    /// ```
+    pub fn new_unbounded<I>(inner: I) -> Self
+    where
+        I: Iterator<Item = T> + Send + 'static,
+    {
+        ReadaheadBuilder::unbounded()
+            .spawn(inner)
+            .expect("failed to spawn readahead_iterator thread")
+    }
+
+    /// Read ahead from a generator closure, as for [`std::iter::from_fn`].
+    ///
+    /// The closure is called repeatedly on the worker thread until it returns
+    /// `None`. Since it moves to the worker, everything it captures must be
+    /// `Send + 'static`.
+    ///
+    /// ```
+    /// use readahead_iterator::Readahead;
+    /// let mut n = 0;
+    /// let squares: Vec<u32> = Readahead::from_generator(
+    ///     move || {
+    ///         n += 1;
+    ///         if n <= 4 { Some(n * n) } else { None }
+    ///     },
+    ///     2,
+    /// )
+    /// .collect();
+    /// assert_eq!(squares, [1, 4, 9, 16]);
+    /// ```
+    ///
+    /// # Async sources
+    ///
+    /// An async `Stream`, such as a database cursor, can be read ahead into a
+    /// synchronous consumer by having the generator block on the next item,
+    /// through a handle to the runtime: with tokio and futures, that's
+    /// `from_generator(move || handle.block_on(stream.next()), n)`. The
+    /// worker thread then sits in `block_on` while it waits, and the runtime
+    /// must still be running. The stream moves to the worker, so it must be
+    /// `Send + 'static`. Don't call `next()` on the `Readahead` from async
+    /// code, since it blocks too. There's no tokio feature in this crate, so
+    /// the caller brings the runtime.
+    pub fn from_generator<F>(f: F, buffer_size: usize) -> Self
+    where
+        F: FnMut() -> Option<T> + Send + 'static,
+    {
+        Readahead::new(std::iter::from_fn(f), buffer_size)
+    }
+
+    /// Read ahead from a closure called repeatedly on the worker thread, as
+    /// for [`std::iter::repeat_with`].
+    ///
+    /// The stream never ends: the worker keeps calling `f` until the buffer is
+    /// full, and stops the next time it tries to send after the `Readahead` is
+    /// dropped.
+    ///
+    /// ```
+    /// use readahead_iterator::Readahead;
+    /// let mut n = 0;
+    /// let evens: Vec<u32> = Readahead::repeat_with(
+    ///     move || {
+    ///         n += 2;
+    ///         n
+    ///     },
+    ///     4,
+    /// )
+    /// .take(3)
+    /// .collect();
+    /// assert_eq!(evens, [2, 4, 6]);
+    /// ```
+    pub fn repeat_with<F>(f: F, buffer_size: usize) -> Self
+    where
+        F: FnMut() -> T + Send + 'static,
+    {
+        Readahead::new(iter::repeat_with(f), buffer_size)
+    }
+
+    /// Read ahead from an iterator built on the worker thread by `factory`.
+    ///
+    /// The iterator needn't be `Send`, since it never leaves the worker: see
+    /// [`ReadaheadBuilder::spawn_with`].
+    ///
+    /// ```
+    /// use std::rc::Rc;
+    /// use readahead_iterator::Readahead;
+    ///
+    /// let v: Vec<u32> = Readahead::from_factory(
+    ///     || {
+    ///         let shared = Rc::new(10);
+    ///         (0..3).map(move |i| i + *shared)
+    ///     },
+    ///     2,
+    /// )
+    /// .collect();
+    /// assert_eq!(v, [10, 11, 12]);
+    /// ```
+    pub fn from_factory<F, I>(factory: F, buffer_size: usize) -> Self
+    where
+        F: FnOnce() -> I + Send + 'static,
+        I: Iterator<Item = T>,
+    {
+        ReadaheadBuilder::new(buffer_size)
+            .spawn_with(factory)
+            .expect("failed to spawn readahead_iterator thread")
+    }
+
+    /// Move `owner` to the worker thread, and read ahead there from an
+    /// iterator that borrows from it, built by `make_iter`.
+    ///
+    /// This solves the common problem of an iterator that can't be sent to
+    /// the worker because it borrows from something else, by sending the
+    /// owner instead. `owner`, `make_iter` and the items must all be
+    /// `Send + 'static`; the boxed iterator needn't be. See
+    /// [`ReadaheadBuilder::spawn_owning`].
+    ///
+    /// ```
+    /// use readahead_iterator::Readahead;
+    ///
+    /// let csv = String::from("a,1\nb,2\nc,3");
+    /// let total: u32 = Readahead::new_owning(
+    ///     csv,
+    ///     |csv| Box::new(csv.lines().map(|line| line[2..].parse::<u32>().unwrap())),
+    ///     4,
+    /// )
+    /// .sum();
+    /// assert_eq!(total, 6);
+    /// ```
+    pub fn new_owning<O, F>(owner: O, make_iter: F, buffer_size: usize) -> Self
+    where
+        O: Send + 'static,
+        F: for<'a> FnOnce(&'a O) -> Box<dyn Iterator<Item = T> + 'a> + Send + 'static,
+    {
+        ReadaheadBuilder::new(buffer_size)
+            .spawn_owning(owner, make_iter)
+            .expect("failed to spawn readahead_iterator thread")
+    }
+
+    /// Consume items from a channel fed by some other producer.
+    ///
+    /// This lets a custom producer, such as a thread reading from a socket,
+    /// reuse the consumer side of `Readahead`.
+    ///
+    /// The producer must send each item as `Some(item)` and then send `None`
+    /// to mark the end of the stream. After `None` is received, the
+    /// `Readahead` returns `None` forever, and does not read from the channel
+    /// again. If all the senders are dropped without sending `None`, this is
+    /// treated as a failure of the producer, and `next()` panics.
+    ///
+    /// ```
+    /// use std::sync::mpsc::sync_channel;
+    /// use std::thread;
+    /// use readahead_iterator::Readahead;
+    ///
+    /// let (sender, receiver) = sync_channel(4);
+    /// thread::spawn(move || {
+    ///     for i in 0..3 {
+    ///         sender.send(Some(i)).unwrap();
+    ///     }
+    ///     sender.send(None).unwrap();
+    /// });
+    /// let v: Vec<u32> = Readahead::from_channel(receiver).collect();
+    /// assert_eq!(v, [0, 1, 2]);
+    /// ```
+    pub fn from_channel(receiver: Receiver<Option<T>>) -> Self {
+        Readahead::from_parts(receiver, None)
+    }
+
+    /// Stop the current worker, and start reading ahead from `inner` in its
+    /// place.
+    ///
+    /// The old worker is stopped before the new one starts, so threads don't
+    /// pile up in a loop: this hangs up on it, drops any buffered items, and
+    /// waits until it's done. That means waiting for any call to the old inner
+    /// iterator's `next()` that's in progress.
+    ///
+    /// The new worker has default settings, as for [`Readahead::new`].
+    ///
+    /// ```
+    /// use readahead_iterator::Readahead;
+    /// let mut rah = Readahead::new(0..1000, 4);
+    /// assert_eq!(rah.next(), Some(0));
+    /// rah.reset(10..12, 4);
+    /// assert_eq!(rah.collect::<Vec<u32>>(), [10, 11]);
+    /// ```
+    pub fn reset<I>(&mut self, inner: I, buffer_size: usize)
+    where
+        I: Iterator<Item = T> + Send + 'static,
+    {
+        let (_, disconnected) = sync_channel(0);
+        let old = mem::replace(self, Readahead::from_parts(disconnected, None));
+        let running = old.shared.clone().filter(|_| old.start.is_none());
+        // Dropping hangs up, and runs any configured interrupt.
+        drop(old);
+        if let Some(shared) = running {
+            shared.wait_done(None);
+        }
+        *self = Readahead::new(inner, buffer_size);
+    }
+
+    /// Borrow items in place, retaining up to `lookbehind` previously consumed
+    /// items so they can also be borrowed.
+    ///
+    /// This avoids moving or cloning large items when an algorithm needs a
+    /// view of recent history. See [`ReadaheadWindow`].
+    pub fn windowed(self, lookbehind: usize) -> ReadaheadWindow<T> {
+        ReadaheadWindow::new(self, lookbehind, 0)
+    }
+
+    /// The number of items of the source consumed so far, counting any
+    /// skipped by [`new_resume`](Readahead::new_resume).
+    ///
+    /// This is a checkpoint from which a later run can resume.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// The number of items yielded by this `Readahead` so far, including any
+    /// passed over by [`nth`](Iterator::nth).
+    ///
+    /// Unlike [`position`](Readahead::position), this doesn't count items
+    /// skipped when resuming. Together with the length of the source, if
+    /// it's known, this is enough to report progress.
+    ///
+    /// ```
+    /// use readahead_iterator::Readahead;
+    ///
+    /// let mut r = Readahead::new_resume(0..10, 4, 3);
+    /// r.next();
+    /// r.next();
+    /// assert_eq!(r.items_yielded(), 2);
+    /// assert_eq!(r.position(), 5);
+    /// ```
+    pub fn items_yielded(&self) -> usize {
+        self.position - self.skipped
+    }
+
+    /// The number of items ready to be returned by `next()` without
+    /// blocking.
+    ///
+    /// If this is more than zero, the next call to `next()` won't block.
+    /// If it's zero, `next()` may block, unless the worker has finished;
+    /// see [`has_more`](Readahead::has_more). The count may grow as soon as
+    /// it's returned, as the worker sends more, but only the consumer takes
+    /// items out, so it can't shrink until `next()` is called.
+    ///
+    /// This is always zero if readahead is [disabled](ReadaheadBuilder::enabled),
+    /// or for an external producer, since nothing is known about the buffer.
+    ///
+    /// ```
+    /// use readahead_iterator::Readahead;
+    ///
+    /// let mut r = Readahead::new(0..10, 4);
+    /// r.warm_up(4);
+    /// assert_eq!(r.buffered_len(), 4);
+    /// while r.buffered_len() > 0 {
+    ///     r.next().unwrap();
+    /// }
+    /// ```
+    pub fn buffered_len(&self) -> usize {
+        self.stats().map_or(0, |stats| stats.buffered())
+    }
+
+    /// A snapshot of how full the buffer is, and has been.
+    ///
+    /// Returns `None` if nothing is known about the buffer: if readahead is
+    /// [disabled](ReadaheadBuilder::enabled), or for an external producer.
+    ///
+    /// The counts are never more than the buffer size. An item the worker
+    /// has finished producing, but is waiting to send into a full buffer,
+    /// isn't counted.
+    ///
+    /// There's no built-in metrics integration: to export these as gauges,
+    /// have the consumer take a snapshot now and then, say every thousand
+    /// items, and record it with whatever metrics library is already in use.
+    ///
+    /// ```
+    /// use readahead_iterator::Readahead;
+    ///
+    /// let mut r = Readahead::new(0..100, 8);
+    /// r.warm_up(8);
+    /// let _ = r.by_ref().count();
+    /// let stats = r.stats().unwrap();
+    /// assert_eq!(stats.buffered(), 0);
+    /// assert_eq!(stats.high_water_mark(), 8);
+    /// ```
+    pub fn stats(&self) -> Option<ReadaheadStats> {
+        let shared = self.shared.as_ref()?;
+        let limit = |n: usize| self.buffer_size.map_or(n, |size| n.min(size));
+        Some(ReadaheadStats {
+            buffered: limit(shared.buffered()),
+            high_water_mark: limit(shared.high_water_mark()),
+        })
+    }
+
+    /// True once the source has ended, even if there are still buffered items
+    /// to consume.
+    ///
+    /// This becomes true as soon as the inner iterator returns `None` on the
+    /// worker, rather than when the consumer gets to the end of the stream,
+    /// so that the consumer can, for example, stop a progress spinner or
+    /// start releasing the producer's resources while it drains the buffer.
+    /// [`has_more`](Readahead::has_more), by contrast, is about what's left
+    /// to consume.
+    ///
+    /// It stays false if the worker stops for any other reason: because it
+    /// panicked, or was stopped early. For an external producer, it becomes
+    /// true when the end of the stream is received.
+    ///
+    /// ```
+    /// use readahead_iterator::Readahead;
+    ///
+    /// let mut r = Readahead::new(0..3, 8);
+    /// r.warm_up(3);
+    /// while !r.source_ended() {
+    ///     std::thread::yield_now();
+    /// }
+    /// assert_eq!(r.next(), Some(0));
+    /// assert!(r.has_more());
+    /// ```
+    pub fn source_ended(&self) -> bool {
+        match &self.shared {
+            Some(shared) => shared.is_source_ended(),
+            None => self.done,
+        }
+    }
+
+    /// Cheaply guess whether there may be more items to come, without
+    /// blocking.
+    ///
+    /// Returns `false` only when the worker has definitely finished and every
+    /// item it produced has been consumed, so the next call to `next()` won't
+    /// block.
+    ///
+    /// A `true` result is only a hint: the worker may finish concurrently
+    /// without producing anything more, in which case `next()` returns `None`.
+    ///
+    /// ```
+    /// use readahead_iterator::Readahead;
+    /// let mut r = Readahead::new(0..2, 10);
+    /// while r.has_more() {
+    ///     if let Some(i) = r.next() {
+    ///         println!("got {}", i);
+    ///     }
+    /// }
+    /// assert_eq!(r.next(), None);
+    /// ```
+    pub fn has_more(&self) -> bool {
+        match &self.shared {
+            _ if self.done => false,
+            Some(shared) => !(shared.is_done() && shared.buffered() == 0),
+            // Nothing is known about an external producer.
+            None => true,
+        }
+    }
+
+    /// Skip over the next `n` items, returning how many were skipped: fewer
+    /// than `n` only if the stream ended.
+    ///
+    /// This is like calling `next()` `n` times and dropping the results, and
+    /// counts towards [`position`](Readahead::position) the same way, but
+    /// skips the per-item checks that `next()` does, as
+    /// [`nth`](Iterator::nth) does. The skipped items have still been produced
+    /// and sent by the worker: to avoid producing them, skip them in the
+    /// source, before reading ahead.
+    ///
+    /// ```
+    /// use readahead_iterator::Readahead;
+    ///
+    /// let mut r = Readahead::new(0..10, 4);
+    /// assert_eq!(r.skip_ahead(3), 3);
+    /// assert_eq!(r.next(), Some(3));
+    /// assert_eq!(r.skip_ahead(100), 6);
+    /// assert_eq!(r.next(), None);
+    /// ```
+    pub fn skip_ahead(&mut self, n: usize) -> usize {
+        // Skipped items were already produced, so just drop them as they
+        // arrive, without the per-item checks in `next()`.
+        for skipped in 0..n {
+            self.start();
+            if self.recv().is_none() {
+                return skipped;
+            }
+        }
+        n
+    }
+
+    /// Take up to `n` items in one call.
+    ///
+    /// This blocks until the first item is available, or the stream ends, and
+    /// then takes as many more items as are already buffered, up to `n` in
+    /// total, without blocking again. So the result has fewer than `n` items
+    /// if the worker hasn't got far enough ahead, and is empty only at the
+    /// end of the stream, or if `n` is zero.
+    ///
+    /// ```
+    /// use readahead_iterator::Readahead;
+    /// let mut rah = Readahead::new(0..5, 10);
+    /// rah.warm_up(5);
+    /// assert_eq!(rah.next_chunk(3), [0, 1, 2]);
+    /// assert_eq!(rah.next_chunk(3), [3, 4]);
+    /// assert!(rah.next_chunk(3).is_empty());
+    /// ```
+    pub fn next_chunk(&mut self, n: usize) -> Vec<T> {
+        let mut chunk = Vec::new();
+        if n == 0 {
+            return chunk;
+        }
+        if let Some(first) = self.next() {
+            chunk.reserve(n.min(self.buffered_hint() + 1));
+            chunk.push(first);
+            while chunk.len() < n {
+                match self.try_recv() {
+                    Some(item) => chunk.push(item),
+                    None => break,
+                }
+            }
+        }
+        chunk
+    }
+
+    /// The number of items known to be buffered, or zero.
+    fn buffered_hint(&self) -> usize {
+        self.shared.as_ref().map_or(0, |shared| shared.buffered())
+    }
+
+    /// Append all the remaining items to `out`.
+    ///
+    /// This is equivalent to `out.extend(self)`, but receives items in a tight
+    /// loop, and reserves space for the items already buffered.
+    ///
+    /// ```
+    /// use readahead_iterator::Readahead;
+    /// let mut v = vec![0, 1];
+    /// Readahead::new(2..5, 10).drain_into(&mut v);
+    /// assert_eq!(v, [0, 1, 2, 3, 4]);
+    /// ```
+    pub fn drain_into(mut self, out: &mut Vec<T>) {
+        self.start();
+        if let Some(shared) = &self.shared {
+            out.reserve(shared.buffered());
+        }
+        while let Some(item) = self.recv() {
+            out.push(item);
+        }
+    }
+
+    /// Stop the worker, and return the items already buffered as an ordinary
+    /// iterator.
+    ///
+    /// This is for the tail of a stream, typically once the source is
+    /// exhausted, when the consumer wants to free the worker thread straight
+    /// away and just process what's left in the buffer. The buffered items
+    /// are collected eagerly into a `Vec` before this returns; then the
+    /// `Readahead` is dropped, which hangs up, so the worker stops without
+    /// producing more than the item it's already working on, and is joined
+    /// if the builder configured
+    /// [`join_on_drop`](ReadaheadBuilder::join_on_drop). Items that weren't
+    /// yet buffered, including any the worker sends as room is made while
+    /// this drains, are dropped, and so is any panic from the worker.
+    ///
+    /// Nothing is buffered if readahead is
+    /// [disabled](ReadaheadBuilder::enabled), or a
+    /// [lazy](ReadaheadBuilder::lazy) worker hasn't started, so then the
+    /// result is empty. For a `Readahead` built with
+    /// [`from_channel`](Readahead::from_channel), this takes items until the
+    /// channel is empty.
+    ///
+    /// ```
+    /// use readahead_iterator::Readahead;
+    ///
+    /// let mut rah = Readahead::new(0..5, 10);
+    /// assert_eq!(rah.next(), Some(0));
+    /// while !rah.source_ended() {
+    ///     std::thread::yield_now();
+    /// }
+    /// let rest: Vec<u32> = rah.into_drained_iter().collect();
+    /// assert_eq!(rest, [1, 2, 3, 4]);
+    /// ```
+    pub fn into_drained_iter(mut self) -> std::vec::IntoIter<T> {
+        let mut items = Vec::new();
+        if self.inline.is_none() && self.start.is_none() {
+            // Take only what's buffered now, so that a fast worker can't
+            // keep this going by refilling the buffer.
+            let limit = match &self.shared {
+                Some(shared) => shared.buffered(),
+                None => usize::MAX,
+            };
+            if limit != usize::MAX {
+                items.reserve(limit);
+            }
+            while items.len() < limit {
+                match self.try_recv() {
+                    Some(item) => items.push(item),
+                    None => break,
+                }
+            }
+        }
+        // Dropping hangs up, and joins the worker if configured.
+        drop(self);
+        items.into_iter()
+    }
+
+    /// Report whether the worker is still running.
+    ///
+    /// This is false once the worker has finished, whether by reaching the end
+    /// of the inner iterator, noticing that the consumer hung up, or
+    /// panicking, even if there are still buffered items to consume. It's
+    /// also false if a [lazy](ReadaheadBuilder::lazy) worker hasn't started.
+    ///
+    /// The answer may be out of date as soon as it's returned: a worker that
+    /// was alive may finish immediately afterwards. The flag is cleared just
+    /// before the worker thread exits, so a thread that is reported dead may
+    /// still be briefly running its last few instructions.
+    ///
+    /// Nothing is known about an external producer, so a `Readahead` built
+    /// with [`from_channel`](Readahead::from_channel) always reports true.
+    pub fn worker_is_alive(&self) -> bool {
+        match &self.shared {
+            _ if self.start.is_some() => false,
+            Some(shared) => !shared.is_done(),
+            None => true,
+        }
+    }
+
+    /// Fold items until `f` breaks, and then stop the worker.
+    ///
+    /// This is like [`Iterator::try_fold`], but consumes the `Readahead`, so
+    /// that on a break the consumer hangs up straight away, and the worker
+    /// stops without producing more than the next item. If the builder
+    /// configured [`join_on_drop`](ReadaheadBuilder::join_on_drop), this also
+    /// waits for the worker to finish, so anything the source holds, such as
+    /// a file lock, has been released by the time this returns.
+    ///
+    /// If `f` never breaks, this is the same as `try_fold`.
+    ///
+    /// ```
+    /// use std::ops::ControlFlow;
+    /// use readahead_iterator::Readahead;
+    ///
+    /// let first_big = Readahead::new(1..1_000_000u64, 10).try_fold_and_stop(0, |sum, i| {
+    ///     if i * i > 50 { ControlFlow::Break(i) } else { ControlFlow::Continue(sum + i) }
+    /// });
+    /// assert_eq!(first_big, ControlFlow::Break(8));
+    /// ```
+    pub fn try_fold_and_stop<B, C, F>(mut self, init: B, mut f: F) -> ControlFlow<C, B>
+    where
+        F: FnMut(B, T) -> ControlFlow<C, B>,
+    {
+        let mut acc = init;
+        while let Some(item) = self.next() {
+            match f(acc, item) {
+                ControlFlow::Continue(next) => acc = next,
+                ControlFlow::Break(value) => {
+                    // Dropping hangs up, and joins the worker if configured.
+                    drop(self);
+                    return ControlFlow::Break(value);
+                }
+            }
+        }
+        ControlFlow::Continue(acc)
+    }
+
+    /// Call `f` on each item, and then wait for the worker to finish, reporting
+    /// whether it panicked.
+    ///
+    /// This is [`Iterator::for_each`], which already overlaps `f` with
+    /// producing the next items, for when the caller wants to know how the
+    /// producer ended rather than have its panic resumed. If the worker
+    /// panics, the items sent before the panic are passed to `f`, and then
+    /// the panic's payload is returned as the error. A panic in `f` itself
+    /// isn't caught.
+    ///
+    /// Before returning, this waits for the worker to finish. If the builder
+    /// configured [`join_on_drop`](ReadaheadBuilder::join_on_drop), the
+    /// thread is joined; otherwise this waits until it's about to exit.
+    ///
+    /// A worker that swallows panics, with
+    /// [`PanicPolicy::Swallow`], just ends the stream, so this returns `Ok`.
+    ///
+    /// ```
+    /// use readahead_iterator::Readahead;
+    ///
+    /// let source = (0..10).map(|i| if i < 3 { i } else { panic!("bad item") });
+    /// let mut seen = Vec::new();
+    /// let result = Readahead::new(source, 4).for_each_with_worker(|i| seen.push(i));
+    /// assert_eq!(seen, [0, 1, 2]);
+    /// assert_eq!(*result.unwrap_err().downcast::<&str>().unwrap(), "bad item");
+    /// ```
+    pub fn for_each_with_worker<F>(mut self, mut f: F) -> Result<(), Box<dyn Any + Send>>
+    where
+        F: FnMut(T),
+    {
+        let result = loop {
+            // Only the panic resumed from the worker is caught, not one in `f`.
+            match panic::catch_unwind(AssertUnwindSafe(|| self.next())) {
+                Ok(Some(item)) => f(item),
+                Ok(None) => break Ok(()),
+                Err(payload) => break Err(payload),
+            }
+        };
+        self.wait_for_worker();
+        result
+    }
+
+    /// Consume the whole stream, and report how long it took and how fast
+    /// items arrived.
+    ///
+    /// This packages the measurement around a benchmark of a readahead
+    /// pipeline. The time runs from this call until the end of the stream, and
+    /// includes the time to receive each item, but nothing is done with the
+    /// items: they're dropped as they arrive. Afterwards this waits for the
+    /// worker to finish, as for
+    /// [`for_each_with_worker`](Readahead::for_each_with_worker). A panic
+    /// from the worker is resumed, as by `next()`.
+    ///
+    /// ```
+    /// use readahead_iterator::Readahead;
+    ///
+    /// let report = Readahead::new(0..1000, 16).drain_timed();
+    /// assert_eq!(report.items(), 1000);
+    /// assert!(report.high_water_mark() <= 16);
+    /// println!("{:.0} items/s", report.items_per_sec());
+    /// ```
+    pub fn drain_timed(mut self) -> DrainReport {
+        let start = Instant::now();
+        let mut items = 0;
+        while self.next().is_some() {
+            items += 1;
+        }
+        let elapsed = start.elapsed();
+        self.wait_for_worker();
+        DrainReport {
+            items,
+            elapsed,
+            high_water_mark: self.stats().map_or(0, |stats| stats.high_water_mark()),
+        }
+    }
+
+    /// Wait for the worker to finish: join the thread if it's joined on drop,
+    /// or otherwise wait until it's about to exit.
+    fn wait_for_worker(&mut self) {
+        if let Some(JoinOnDrop {
+            handle: Some(handle),
+            ..
+        }) = self.join_on_drop.take()
+        {
+            // Any panic was already passed on through the shared state.
+            let _ = handle.join();
+        } else if let Some(shared) = &self.shared {
+            shared.wait_done(None);
+        }
+    }
+
+    /// Block until `target` items are buffered, or the worker finishes.
+    ///
+    /// This "primes the pump" so that the following calls to `next()` return
+    /// immediately. `target` is clamped to the buffer size, since the worker
+    /// can't get further ahead than that.
+    ///
+    /// This returns immediately for a `Readahead` built with
+    /// [`from_channel`](Readahead::from_channel), since the state of an
+    /// external producer isn't known.
+    ///
+    /// ```
+    /// use readahead_iterator::Readahead;
+    /// let rah = Readahead::new(0..100, 10);
+    /// rah.warm_up(10);
+    /// assert!(rah.has_more());
+    /// ```
+    pub fn warm_up(&self, target: usize) {
+        if self.start.is_some() {
+            return;
+        }
+        if let (Some(shared), false) = (&self.shared, self.done) {
+            let mut target = self.buffer_size.map_or(target, |size| target.min(size));
+            if self.grow_to.is_some() {
+                target = target.min(shared.limit());
+            }
+            shared.wait_until(|s| s.buffered() >= target || s.is_done(), None);
+        }
+    }
+
+    /// Stop the worker from producing more items, until
+    /// [`resume`](Readahead::resume) is called.
+    ///
+    /// This is flow control for when memory is short: the worker stops
+    /// filling the buffer, but the `Readahead` stays ready to carry on. Items
+    /// already buffered can still be consumed.
+    ///
+    /// The pause takes effect between items: if the worker is inside the
+    /// inner iterator's `next()`, it finishes that item and sends it, if
+    /// there's room, before waiting. While paused, a call to `next()` with
+    /// nothing buffered blocks until another thread resumes the worker, so a
+    /// consumer on a single thread must resume before it reads more than was
+    /// already buffered.
+    ///
+    /// Dropping a paused `Readahead` stops the worker as usual. This has no
+    /// effect if readahead is [disabled](ReadaheadBuilder::enabled), or for an
+    /// external producer.
+    ///
+    /// ```
+    /// use readahead_iterator::Readahead;
+    ///
+    /// let mut r = Readahead::new(0..10, 2);
+    /// r.warm_up(2);
+    /// r.pause();
+    /// // What's already buffered can still be read.
+    /// assert_eq!(r.next(), Some(0));
+    /// assert_eq!(r.next(), Some(1));
+    /// r.resume();
+    /// assert_eq!(r.last(), Some(9));
+    /// ```
+    pub fn pause(&self) {
+        if let Some(shared) = &self.shared {
+            shared.set_paused(true);
+        }
+    }
+
+    /// Let the worker carry on producing items after
+    /// [`pause`](Readahead::pause).
+    ///
+    /// This does nothing if the worker isn't paused.
+    pub fn resume(&self) {
+        if let Some(shared) = &self.shared {
+            shared.set_paused(false);
+        }
+    }
+
+    /// True if the worker is paused by [`pause`](Readahead::pause).
+    pub fn is_paused(&self) -> bool {
+        self.shared
+            .as_ref()
+            .is_some_and(|shared| shared.is_paused())
+    }
+
+    /// Spawn the worker, if it was deferred with [`ReadaheadBuilder::lazy`].
+    fn start(&mut self) {
+        if let Some(start) = self.start.take() {
+            let handle = start().expect("failed to spawn readahead_iterator thread");
+            if let Some(join_on_drop) = &mut self.join_on_drop {
+                join_on_drop.handle = Some(handle);
+            }
+        }
+    }
+
+    /// Called by `next()`, when configured, before receiving an item.
+    fn before_recv(&mut self) {
+        self.start();
+        if let (Some(check), Some(shared)) = (&mut self.degenerate_check, &self.shared) {
+            check.observe(shared.buffered() == 0);
+        }
+        if let (Some(max), Some(shared)) = (self.grow_to, &self.shared) {
+            if shared.buffered() == 0 && self.position > 0 && !shared.is_done() {
+                shared.grow_limit(max);
+            }
+        }
+        if let (Some(mut n), Some(shared)) = (self.min_batch, &self.shared) {
+            if shared.buffered() == 0 {
+                // An adaptive buffer may not yet have room for the batch.
+                if self.grow_to.is_some() {
+                    n = n.min(shared.limit());
+                }
+                shared.wait_for_batch(n);
+            }
+        }
+    }
+
+    /// Receive the next item, handling the end of the stream.
+    fn recv(&mut self) -> Option<T> {
+        if self.held.is_some() {
+            return self.recv_held(true);
+        }
+        self.recv_oldest()
+    }
+
+    /// Receive the next item from the channel, handling the end of the stream.
+    fn recv_oldest(&mut self) -> Option<T> {
+        if self.done {
+            return None;
+        }
+        if let Some(inner) = &mut self.inline {
+            let item = inner.next();
+            self.done = item.is_none();
+            self.position += item.is_some() as usize;
+            return item;
+        }
+        if cfg!(debug_assertions) {
+            self.check_not_worker();
+        }
+        let received = match &self.pool_task {
+            None => self.receiver.recv().ok(),
+            // Rather than waiting for the pool to get around to the producer,
+            // run it here until there's something to receive.
+            Some(task) => loop {
+                match self.receiver.try_recv() {
+                    Ok(received) => break Some(received),
+                    Err(TryRecvError::Empty) => task.step(),
+                    Err(TryRecvError::Disconnected) => break None,
+                }
+            },
+        };
+        match received {
+            Some(received) => self.accept(received),
+            None => self.producer_failed(),
+        }
+    }
+
+    /// Panic if this would block the worker thread waiting for itself, which
+    /// can happen if the inner iterator somehow reaches its own `Readahead`.
+    fn check_not_worker(&self) {
+        if let Some(shared) = &self.shared {
+            if shared.buffered() == 0 && !shared.is_done() && shared.is_worker_thread() {
+                panic!(
+                    "readahead_iterator: next() called on the worker thread of the same \
+                     Readahead with an empty buffer; this would deadlock"
+                );
+            }
+        }
+    }
+
+    /// Receive the next item, waiting at most `timeout`.
+    ///
+    /// Returns `None` if the timeout expires, and `Some(None)` at the end of
+    /// the stream. Producers that run on the consumer thread are waited for
+    /// without a timeout.
+    pub(crate) fn recv_timeout(&mut self, timeout: Duration) -> Option<Option<T>> {
+        if self.done || self.inline.is_some() || self.pool_task.is_some() {
+            return Some(self.recv());
+        }
+        self.start();
+        match self.receiver.recv_timeout(timeout) {
+            Ok(received) => Some(self.accept(received)),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => self.producer_failed(),
+        }
+    }
+
+    /// Receive an item if one is already buffered, without blocking.
+    ///
+    /// Returns `None` if nothing's buffered, or at the end of the stream.
+    fn try_recv(&mut self) -> Option<T> {
+        if self.held.is_some() {
+            return self.recv_held(false);
+        }
+        self.try_recv_oldest()
+    }
+
+    /// Receive an item from the channel if one is already buffered, without
+    /// blocking.
+    fn try_recv_oldest(&mut self) -> Option<T> {
+        if self.done {
+            return None;
+        }
+        if let Some(inner) = &mut self.inline {
+            let item = inner.next();
+            self.done = item.is_none();
+            self.position += item.is_some() as usize;
+            return item;
+        }
+        match self.receiver.try_recv() {
+            Ok(received) => self.accept(received),
+            // A failed producer is reported by the next blocking receive.
+            Err(_) => None,
+        }
+    }
+
+    /// For LIFO or priority delivery: take everything already in the
+    /// channel, and then deliver the first of the items held, waiting for one
+    /// if `block` is true.
+    fn recv_held(&mut self, block: bool) -> Option<T> {
+        while let Some(item) = self.try_recv_oldest() {
+            self.held.as_mut().unwrap().hold(item);
+        }
+        let item = match self.held.as_mut().unwrap().take_next() {
+            Some(item) => item,
+            None if block => self.recv_oldest()?,
+            None => return None,
+        };
+        self.did_deliver();
+        Some(item)
+    }
+
+    /// Count an item delivered to the caller.
+    fn did_deliver(&mut self) {
+        self.position += 1;
+        if let Some(shared) = &self.shared {
+            shared.did_receive();
+        }
+    }
+
+    /// Account for an item, or the end of the stream, received from the
+    /// channel.
+    fn accept(&mut self, received: Option<T>) -> Option<T> {
+        match received {
+            Some(item) => {
+                // Items held for LIFO or priority delivery still count as
+                // buffered until they're delivered.
+                if self.held.is_none() {
+                    self.did_deliver();
+                }
+                if let Some(shared) = &self.shared {
+                    if self.bytes_limited {
+                        shared.remove_bytes();
+                    }
+                }
+                if let Some(check) = &mut self.ordering_check {
+                    check.observe();
+                }
+                if let Some(task) = &self.pool_task {
+                    // Refill the buffer in the background.
+                    task.clone().wake();
+                }
+                Some(item)
+            }
+            None => {
+                self.done = true;
+                None
+            }
+        }
+    }
+
+    /// The producer hung up without sending the end of the stream: resume
+    /// its panic, if it passed one.
+    fn producer_failed(&self) -> ! {
+        if let Some(payload) = self.shared.as_ref().and_then(|shared| shared.take_panic()) {
+            panic::resume_unwind(payload);
+        }
+        panic!("recv of iterator value failed")
+    }
+
+    pub(crate) fn from_parts(receiver: Receiver<Option<T>>, shared: Option<Arc<Shared>>) -> Self {
+        Readahead {
+            receiver,
+            done: false,
+            position: 0,
+            skipped: 0,
+            shared,
+            join_on_drop: None,
+            degenerate_check: None,
+            ordering_check: None,
+            drop_in_order: false,
+            linger: None,
+            bytes_limited: false,
+            interrupt: None,
+            buffer_size: None,
+            start: None,
+            pool_task: None,
+            inline: None,
+            held: None,
+            min_batch: None,
+            grow_to: None,
+            before_recv: false,
+        }
+    }
+}
+
+impl<T> Iterator for Readahead<T>
+where
+    T: Send + 'static,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.before_recv && !self.done {
+            self.before_recv();
+        }
+        self.recv()
+    }
+
+    fn nth(&mut self, n: usize) -> Option<T> {
+        if self.skip_ahead(n) < n {
+            return None;
+        }
+        self.next()
+    }
+}
+
+impl<T> Drop for Readahead<T>
+where
+    T: Send + 'static,
+{
+    fn drop(&mut self) {
+        if let Some(shared) = &self.shared {
+            shared.hang_up();
+        }
+        if self.drop_in_order && !self.done {
+            while let Ok(Some(item)) = self.receiver.try_recv() {
+                drop(item);
+            }
+        }
+        if let (Some(interrupt), Some(shared)) = (self.interrupt.take(), &self.shared) {
+            if !shared.is_done() && self.start.is_none() {
+                // Hang up first, so that the worker stops once it's
+                // interrupted, rather than going on to the next item.
+                let (_, disconnected) = sync_channel(0);
+                drop(mem::replace(&mut self.receiver, disconnected));
+                interrupt();
+            }
+        }
+        if let (Some(linger), Some(shared), None) = (self.linger, &self.shared, &self.start) {
+            // Hang up, so that the worker doesn't start another item.
+            let (_, disconnected) = sync_channel(0);
+            drop(mem::replace(&mut self.receiver, disconnected));
+            shared.wait_until(|s| !s.is_producing() || s.is_done(), Some(linger));
+        }
+        if let Some(JoinOnDrop {
+            handle: Some(handle),
+            shared,
+            timeout,
+        }) = self.join_on_drop.take()
+        {
+            // Hang up first, so that a worker blocked on a full buffer stops.
+            let (_, disconnected) = sync_channel(0);
+            drop(mem::replace(&mut self.receiver, disconnected));
+            if shared.wait_done(timeout) {
+                // A panic on the worker has already been reported by the panic
+                // hook, and there's nobody to pass it to.
+                let _ = handle.join();
+            }
+        }
+    }
+}
+
+/// Shows the state of the readahead without receiving anything or blocking.
+///
+/// ```
+/// use readahead_iterator::Readahead;
+/// let rah = Readahead::new(0..10, 4);
+/// assert!(format!("{:?}", rah).starts_with("Readahead { exhausted: false"));
+/// ```
+impl<T> fmt::Debug for Readahead<T>
+where
+    T: Send + 'static,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Readahead")
+            .field("exhausted", &self.done)
+            .field("buffer_size", &self.buffer_size)
+            .field("buffered", &self.shared.as_ref().map(|s| s.buffered()))
+            .field("worker_is_alive", &self.worker_is_alive())
+            .finish()
+    }
+}
+
+/// Adds a `.readahead(buffer_size)` method to any iterator.
+///
+/// ```
+/// use readahead_iterator::IntoReadahead;
+///
+/// let c = "Some input data".chars()
+///     .readahead(10)
+///     .filter(|c| c.is_alphabetic())
+///     .count();
+/// # assert_eq!(c, 13);
+/// ```
+///
+/// # `Send` and `'static`
+///
+/// The source runs on another thread, so it and its items must be `Send`,
+/// and must not borrow from the caller's stack: they must be `'static`. The
+/// compiler errors when they aren't can be obscure. The usual causes are:
+///
+/// * An iterator that borrows a collection, such as `vec.iter()`, which is
+///   reported as the collection not living long enough. Move the collection
+///   to the worker with `into_iter()`, or
+///   [`Readahead::from_into_iter`], or clone the items first.
+///
+/// * An `Rc` or `RefCell` captured by a closure in the source, reported as
+///   "cannot be sent between threads safely". Use `Arc` and `Mutex`, or build
+///   the source on the worker with [`ReadaheadBuilder::spawn_with`].
+pub trait IntoReadahead<T>
+where
+    T: Send + 'static,
+{
+    /// Apply a readahead adaptor to an iterator.
+    ///
+    /// `buffer_size` is the maximum number of buffered items.
+    ///
+    /// # With rayon
+    ///
+    /// A `Readahead` is itself a `Send` iterator, so it can feed a
+    /// [rayon](https://docs.rs/rayon) parallel pipeline through rayon's
+    /// `par_bridge`: the worker does the IO, overlapped with rayon's pool
+    /// doing the CPU work on the items. The items must be `Send`, as they
+    /// already are for readahead. `par_bridge` doesn't keep the order of the
+    /// items; collect them with their indexes, or use
+    /// [`readahead_map_parallel_chunks`](IntoReadahead::readahead_map_parallel_chunks),
+    /// if order matters. There's no rayon feature in this crate, so call
+    /// `par_bridge` on the `Readahead` directly.
+    #[must_use = "dropping the Readahead straight away stops its worker thread"]
+    fn readahead(self, buffer_size: usize) -> Readahead<T>
+    where
+        Self: Send + 'static;
+
+    /// Endlessly repeat the iterator on the worker thread, as for
+    /// [`Iterator::cycle`], reading ahead from the repetition.
+    ///
+    /// Like `cycle`, this keeps two copies of the iterator on the worker: the
+    /// original, and a clone being worked through, which is replaced by a new
+    /// clone each time it runs out. As always, the worker can only get
+    /// `buffer_size` items ahead of the consumer, so although the output
+    /// never ends, the buffer doesn't grow.
+    ///
+    /// This never returns `None` unless the iterator is empty. The worker stops
+    /// when the `Readahead` is dropped.
+    ///
+    /// ```
+    /// use readahead_iterator::IntoReadahead;
+    ///
+    /// let v: Vec<char> = "abc".chars().readahead_cycle(4).take(7).collect();
+    /// assert_eq!(v, ['a', 'b', 'c', 'a', 'b', 'c', 'a']);
+    /// ```
+    fn readahead_cycle(self, buffer_size: usize) -> Readahead<T>
+    where
+        Self: Clone + Send + 'static;
+
+    /// Map items on the worker thread until `f` returns `None`, as for
+    /// [`Iterator::map_while`], reading ahead from the results.
+    ///
+    /// The worker stops pulling from the inner iterator as soon as `f` returns
+    /// `None`, so no items past that point are produced.
+    ///
+    /// ```
+    /// use readahead_iterator::IntoReadahead;
+    ///
+    /// let v: Vec<u32> = ["1", "2", "x", "4"]
+    ///     .iter()
+    ///     .readahead_map_while(|s| s.parse().ok(), 4)
+    ///     .collect();
+    /// assert_eq!(v, [1, 2]);
+    /// ```
+    fn readahead_map_while<U, F>(self, f: F, buffer_size: usize) -> Readahead<U>
+    where
+        Self: Send + 'static,
+        F: FnMut(T) -> Option<U> + Send + 'static,
+        U: Send + 'static;
+
+    /// Read ahead from this and another iterator, each on its own worker
+    /// thread, and zip their items together on the consumer thread.
+    ///
+    /// Both iterators, and their items, must be `Send + 'static`.
+    ///
+    /// The result ends when either iterator ends. Both readaheads are then
+    /// dropped, so the other worker stops too, without waiting for the result
+    /// to be dropped.
+    ///
+    /// ```
+    /// use readahead_iterator::IntoReadahead;
+    ///
+    /// let v: Vec<(char, u32)> = "abc".chars().readahead_zip(0.., 4).collect();
+    /// assert_eq!(v, [('a', 0), ('b', 1), ('c', 2)]);
+    /// ```
+    fn readahead_zip<J>(self, other: J, buffer_size: usize) -> ReadaheadZip<T, J::Item>
+    where
+        Self: Send + 'static,
+        J: Iterator + Send + 'static,
+        J::Item: Send + 'static;
+
+    /// Run a stateful transform on the worker thread, as for
+    /// [`Iterator::scan`], reading ahead from the results.
+    ///
+    /// The state is owned by the worker thread, so it need only be `Send`, not
+    /// `Sync`. The output ends when `f` returns `None`.
+    ///
+    /// ```
+    /// use readahead_iterator::IntoReadahead;
+    ///
+    /// let running_totals: Vec<u32> = (1..=5)
+    ///     .readahead_scan(0, |total, i| {
+    ///         *total += i;
+    ///         Some(*total)
+    ///     }, 4)
+    ///     .collect();
+    /// assert_eq!(running_totals, [1, 3, 6, 10, 15]);
+    /// ```
+    fn readahead_scan<St, U, F>(self, init: St, f: F, buffer_size: usize) -> Readahead<U>
+    where
+        Self: Send + 'static,
+        St: Send + 'static,
+        F: FnMut(&mut St, T) -> Option<U> + Send + 'static,
+        U: Send + 'static;
+
+    /// Take every `step`-th item on the worker thread, as for
+    /// [`Iterator::step_by`], reading ahead from the results.
+    ///
+    /// The worker still has to produce every item from the inner iterator,
+    /// since iterators can't in general skip items without producing them.
+    /// But the skipped items are dropped on the worker thread, rather than
+    /// being sent to the consumer only to be discarded.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `step` is 0.
+    ///
+    /// ```
+    /// use readahead_iterator::IntoReadahead;
+    ///
+    /// let v: Vec<u32> = (0..10).readahead_step_by(3, 4).collect();
+    /// assert_eq!(v, [0, 3, 6, 9]);
+    /// ```
+    fn readahead_step_by(self, step: usize, buffer_size: usize) -> Readahead<T>
+    where
+        Self: Send + 'static;
+
+    /// Filter items on the worker thread, as for [`Iterator::filter`], reading
+    /// ahead from the items that pass.
+    ///
+    /// This is the same as `.filter(predicate).readahead(buffer_size)`, but
+    /// makes it explicit that rejected items are dropped on the worker and
+    /// never sent to the consumer, which matters when items are large. By
+    /// contrast, `.readahead(buffer_size).filter(predicate)` sends every item
+    /// and filters on the consumer thread.
+    ///
+    /// ```
+    /// use readahead_iterator::IntoReadahead;
+    ///
+    /// let v: Vec<u32> = (0..10).readahead_filter(|i| i % 3 == 0, 4).collect();
+    /// assert_eq!(v, [0, 3, 6, 9]);
+    /// ```
+    fn readahead_filter<P>(self, predicate: P, buffer_size: usize) -> Readahead<T>
+    where
+        Self: Send + 'static,
+        P: FnMut(&T) -> bool + Send + 'static;
+
+    /// Run a side effect on each item on the worker thread, as for
+    /// [`Iterator::inspect`], before it's buffered.
+    ///
+    /// This moves work such as logging or progress reporting off the
+    /// consumer's thread. `f` sees items in source order.
+    ///
+    /// ```
+    /// use std::sync::{Arc, Mutex};
+    /// use readahead_iterator::IntoReadahead;
+    ///
+    /// let seen = Arc::new(Mutex::new(Vec::new()));
+    /// let seen_clone = seen.clone();
+    /// let sum: u32 = (1..=4)
+    ///     .readahead_inspect(move |i| seen_clone.lock().unwrap().push(*i), 2)
+    ///     .sum();
+    /// assert_eq!(sum, 10);
+    /// assert_eq!(*seen.lock().unwrap(), [1, 2, 3, 4]);
+    /// ```
+    fn readahead_inspect<F>(self, f: F, buffer_size: usize) -> Readahead<T>
+    where
+        Self: Send + 'static,
+        F: FnMut(&T) + Send + 'static;
+
+    /// Apply a readahead adaptor, returning an error if the worker thread
+    /// can't be spawned.
+    ///
+    /// This is the fallible counterpart of [`readahead`](IntoReadahead::readahead),
+    /// like [`Readahead::try_new`].
+    ///
+    /// ```
+    /// use readahead_iterator::IntoReadahead;
+    ///
+    /// let sum: u32 = (1..=4).try_readahead(2)?.sum();
+    /// assert_eq!(sum, 10);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    fn try_readahead(self, buffer_size: usize) -> io::Result<Readahead<T>>
+    where
+        Self: Send + 'static;
+
+    /// Take at most `n` items on the worker thread, as for [`Iterator::take`],
+    /// reading ahead from them.
+    ///
+    /// The worker stops after producing `n` items, whereas
+    /// `.readahead(buffer_size).take(n)` may produce up to `buffer_size` more
+    /// items than are ever consumed.
+    ///
+    /// ```
+    /// use readahead_iterator::IntoReadahead;
+    ///
+    /// let v: Vec<u32> = (0..).readahead_take(3, 10).collect();
+    /// assert_eq!(v, [0, 1, 2]);
+    /// ```
+    fn readahead_take(self, n: usize, buffer_size: usize) -> Readahead<T>
+    where
+        Self: Send + 'static;
+
+    /// Find the last item on the worker thread, and send only that.
+    ///
+    /// The worker runs the iterator to completion, keeping only the most
+    /// recent item, so the discarded items never cross the channel as they
+    /// would with `.readahead(buffer_size).last()`. The result yields the last
+    /// item, or nothing if the iterator was empty. The consumer can do other
+    /// work until it calls `next()`.
+    ///
+    /// ```
+    /// use readahead_iterator::IntoReadahead;
+    ///
+    /// let mut last = (0..1000).map(|i| vec![i; 100]).readahead_last();
+    /// assert_eq!(last.next().map(|v| v[0]), Some(999));
+    /// assert_eq!(last.next(), None);
+    /// ```
+    fn readahead_last(self) -> Readahead<T>
+    where
+        Self: Send + 'static;
+
+    /// Apply a readahead adaptor that can also peek at the next item.
+    ///
+    /// See [`ReadaheadPeekable`].
+    fn readahead_peekable(self, buffer_size: usize) -> ReadaheadPeekable<T>
+    where
+        Self: Send + 'static;
+
+    /// Group runs of consecutive items with equal keys on the worker thread,
+    /// reading ahead from the groups.
+    ///
+    /// Each group is sent as the key and a `Vec` of its items, once the first
+    /// item of the following group is seen, or at the end of the stream. Items
+    /// with equal keys that aren't adjacent are in separate groups.
+    ///
+    /// ```
+    /// use readahead_iterator::IntoReadahead;
+    ///
+    /// let groups: Vec<(bool, Vec<u32>)> = vec![1, 3, 2, 4, 6, 5]
+    ///     .into_iter()
+    ///     .readahead_group_by(|i| i % 2 == 0, 4)
+    ///     .collect();
+    /// assert_eq!(
+    ///     groups,
+    ///     [(false, vec![1, 3]), (true, vec![2, 4, 6]), (false, vec![5])]
+    /// );
+    /// ```
+    fn readahead_group_by<K, F>(self, key_fn: F, buffer_size: usize) -> Readahead<(K, Vec<T>)>
+    where
+        Self: Send + 'static,
+        F: FnMut(&T) -> K + Send + 'static,
+        K: PartialEq + Send + 'static;
+
+    /// Map each item to an iterable on the worker thread, and read ahead from
+    /// the flattened results, as for [`Iterator::flat_map`].
+    ///
+    /// Both `f` and the iteration of each sub-iterator run on the worker, which
+    /// overlaps expensive expansion with the consumer. The sub-iterators are
+    /// created and used only on the worker, so they needn't be `Send`.
+    ///
+    /// If a sub-iterator is infinite, the worker keeps producing from it, and
+    /// never reaches the next item of the source. When the consumer is dropped
+    /// the worker stops the next time it tries to send, but a sub-iterator that
+    /// runs forever without yielding anything keeps the worker busy forever.
+    ///
+    /// ```
+    /// use readahead_iterator::IntoReadahead;
+    ///
+    /// let v: Vec<u32> = (1..=3).readahead_flat_map(|i| 0..i, 4).collect();
+    /// assert_eq!(v, [0, 0, 1, 0, 1, 2]);
+    /// ```
+    fn readahead_flat_map<U, F>(self, f: F, buffer_size: usize) -> Readahead<U::Item>
+    where
+        Self: Send + 'static,
+        F: FnMut(T) -> U + Send + 'static,
+        U: IntoIterator,
+        U::Item: Send + 'static;
+
+    /// Split items between two readaheads on a single worker thread: those
+    /// matching `predicate`, and the rest.
+    ///
+    /// The source is read once, and each item is sent to one of the two
+    /// outputs, in order. Each output has its own buffer of up to
+    /// `buffer_size` items. When the buffer for one output is full, the worker
+    /// waits for it to be read, even if the other output is waiting for items,
+    /// so the two outputs should be consumed concurrently, or the one
+    /// consumed first should be expected to have few items.
+    ///
+    /// If one output is dropped, its items are discarded and the worker keeps
+    /// feeding the other. The worker stops when both are dropped. If the inner
+    /// iterator panics, the panic is resumed from the first output, and the
+    /// second panics with a generic message.
+    ///
+    /// ```
+    /// use std::thread;
+    /// use readahead_iterator::IntoReadahead;
+    ///
+    /// let (evens, odds) = (0..10u32).readahead_partition(|i| i % 2 == 0, 2);
+    /// let odds = thread::spawn(move || odds.collect::<Vec<u32>>());
+    /// assert_eq!(evens.collect::<Vec<u32>>(), [0, 2, 4, 6, 8]);
+    /// assert_eq!(odds.join().unwrap(), [1, 3, 5, 7, 9]);
+    /// ```
+    fn readahead_partition<P>(
+        self,
+        predicate: P,
+        buffer_size: usize,
+    ) -> (Readahead<T>, Readahead<T>)
+    where
+        Self: Send + 'static,
+        P: FnMut(&T) -> bool + Send + 'static;
+
+    /// Map items on the worker thread with access to a context created once,
+    /// on the worker, by `init`.
+    ///
+    /// This suits per-thread resources that are expensive to create, such as
+    /// a scratch buffer or a database connection. The context is created,
+    /// used, and dropped only on the worker thread, so it needn't be `Send`:
+    /// only `init` and `f` move to the worker.
+    ///
+    /// ```
+    /// use readahead_iterator::IntoReadahead;
+    ///
+    /// let lines: Vec<String> = (1..=3)
+    ///     .readahead_with_context(
+    ///         || String::with_capacity(64),
+    ///         |scratch, i| {
+    ///             scratch.clear();
+    ///             scratch.push_str(&"*".repeat(i));
+    ///             scratch.clone()
+    ///         },
+    ///         2,
+    ///     )
+    ///     .collect();
+    /// assert_eq!(lines, ["*", "**", "***"]);
+    /// ```
+    fn readahead_with_context<Ctx, U, Init, F>(
+        self,
+        init: Init,
+        f: F,
+        buffer_size: usize,
+    ) -> Readahead<U>
+    where
+        Self: Send + 'static,
+        Init: FnOnce() -> Ctx + Send + 'static,
+        F: FnMut(&mut Ctx, T) -> U + Send + 'static,
+        U: Send + 'static;
+
+    /// Read ahead, reordering items on the worker thread to emit them in
+    /// sorted order, as far as a window of `buffer_size` items allows.
+    ///
+    /// The worker holds up to `buffer_size` items besides those in the buffer,
+    /// and each time it has more than that it sends the smallest. The output
+    /// is fully sorted only if no item is more than `buffer_size` places
+    /// after where it belongs, as in a nearly-sorted source with bounded
+    /// disorder. Otherwise ordering is best-effort: every item is still
+    /// produced exactly once.
+    ///
+    /// ```
+    /// use readahead_iterator::IntoReadahead;
+    ///
+    /// let v: Vec<u32> = vec![2, 1, 3, 5, 4, 6]
+    ///     .into_iter()
+    ///     .readahead_sorted(2)
+    ///     .collect();
+    /// assert_eq!(v, [1, 2, 3, 4, 5, 6]);
+    /// ```
+    fn readahead_sorted(self, buffer_size: usize) -> Readahead<T>
+    where
+        Self: Send + 'static,
+        T: Ord;
+
+    /// Produce overlapping windows of `size` consecutive items on the worker
+    /// thread, and read ahead from them, as for [`slice::windows`].
+    ///
+    /// Each window is a new `Vec` of cloned items. Only full windows are
+    /// produced: if the source has fewer than `size` items there are none,
+    /// and no shorter windows are produced at the end of the stream.
+    ///
+    /// Panics if `size` is zero.
+    ///
+    /// ```
+    /// use readahead_iterator::IntoReadahead;
+    ///
+    /// let sums: Vec<u32> = (1..=5)
+    ///     .readahead_windows(3, 4)
+    ///     .map(|w| w.iter().sum())
+    ///     .collect();
+    /// assert_eq!(sums, [6, 9, 12]);
+    /// ```
+    fn readahead_windows(self, size: usize, buffer_size: usize) -> Readahead<Vec<T>>
+    where
+        Self: Send + 'static,
+        T: Clone;
+
+    /// Group items into arrays of exactly `N` on the worker thread, and read
+    /// ahead from the arrays.
+    ///
+    /// Fixed-size arrays let the consumer index them without bounds checks,
+    /// which suits SIMD-friendly code. Any items left over at the end are
+    /// available from [`ReadaheadChunksExact::remainder`].
+    ///
+    /// Panics if `N` is zero.
+    ///
+    /// ```
+    /// use readahead_iterator::IntoReadahead;
+    ///
+    /// let sums: Vec<u32> = (0..8)
+    ///     .readahead_chunks_exact::<4>(2)
+    ///     .map(|[a, b, c, d]| a + b + c + d)
+    ///     .collect();
+    /// assert_eq!(sums, [6, 22]);
+    /// ```
+    fn readahead_chunks_exact<const N: usize>(
+        self,
+        buffer_size: usize,
+    ) -> ReadaheadChunksExact<T, N>
+    where
+        Self: Send + 'static;
+
+    /// Read ahead, wrapping each item in an [`Ack`] that calls `on_ack` on the
+    /// worker thread once the consumer drops it.
+    ///
+    /// This suits sources that hold a resource per item, such as a lease or a
+    /// lock, that can only be released once the item has been fully processed
+    /// downstream. `on_ack` is called with the index of the item in the
+    /// stream, counting from zero.
+    ///
+    /// Acks are processed on the worker between items, and, after the end of
+    /// the stream, until every item is acknowledged. An item is acknowledged
+    /// when its `Ack` is dropped, including when the `Readahead` is dropped
+    /// with items still in the buffer. Acks arrive in the order the `Ack`s are
+    /// dropped, which can differ from the order of the items if the consumer
+    /// holds on to some of them. The worker thread doesn't exit until every
+    /// `Ack` is dropped.
+    ///
+    /// ```
+    /// use std::sync::{Arc, Mutex};
+    /// use readahead_iterator::IntoReadahead;
+    ///
+    /// let acked = Arc::new(Mutex::new(Vec::new()));
+    /// let acked_clone = acked.clone();
+    /// let mut rah = vec!["a", "b", "c"]
+    ///     .into_iter()
+    ///     .readahead_acked(move |i| acked_clone.lock().unwrap().push(i), 4);
+    /// let a = rah.next().unwrap();
+    /// let b = rah.next().unwrap();
+    /// assert_eq!((*a, b.index()), ("a", 1));
+    /// drop(b);
+    /// drop(a);
+    /// drop(rah.next());
+    /// assert!(rah.next().is_none());
+    /// # while acked.lock().unwrap().len() < 3 { std::thread::yield_now() }
+    /// ```
+    fn readahead_acked<F>(self, on_ack: F, buffer_size: usize) -> Readahead<Ack<T>>
+    where
+        Self: Send + 'static,
+        F: FnMut(usize) + Send + 'static;
+
+    /// Count the items on the worker thread, and send only the total.
+    ///
+    /// The items are dropped on the worker as they're produced, so they never
+    /// cross the channel as they would with `.readahead(buffer_size).count()`,
+    /// which matters for large items when only the number is needed. The
+    /// result yields the count once the iterator is exhausted. The consumer
+    /// can do other work until it calls `next()`.
+    ///
+    /// ```
+    /// use readahead_iterator::IntoReadahead;
+    ///
+    /// let mut count = (0..1000).map(|i| vec![i; 100]).readahead_count();
+    /// assert_eq!(count.next(), Some(1000));
+    /// ```
+    fn readahead_count(self) -> Readahead<usize>
+    where
+        Self: Send + 'static;
+
+    /// Batch items produced within a time window into `Vec`s, on the worker.
+    ///
+    /// Each batch starts with the next item produced, and is sent when either
+    /// `window` has elapsed since that first item, or `max` items have been
+    /// collected, whichever comes first. At the end of the stream any partial
+    /// batch is sent. Batches are never empty.
+    ///
+    /// This is a micro-batching pattern for downstream bulk operations, such
+    /// as database inserts. So that a batch can be closed while waiting for
+    /// the next item, the inner iterator runs on its own thread, buffering up
+    /// to `max` items, and the batches are formed on a second worker thread.
+    ///
+    /// Panics if `max` is zero.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use readahead_iterator::IntoReadahead;
+    ///
+    /// let batches: Vec<Vec<u32>> = (0..10)
+    ///     .readahead_coalesce_time(Duration::from_secs(10), 4, 2)
+    ///     .collect();
+    /// assert_eq!(batches, [vec![0, 1, 2, 3], vec![4, 5, 6, 7], vec![8, 9]]);
+    /// ```
+    fn readahead_coalesce_time(
+        self,
+        window: Duration,
+        max: usize,
+        buffer_size: usize,
+    ) -> Readahead<Vec<T>>
+    where
+        Self: Send + 'static;
+
+    /// Read ahead, delivering each item with the number of items that were
+    /// already buffered when the worker sent it.
+    ///
+    /// A depth that's usually close to `buffer_size` means the consumer is
+    /// the bottleneck; close to zero means the producer is.
+    ///
+    /// The depth is a snapshot, read by the worker just after producing the
+    /// item: the consumer may take items from the buffer between the snapshot
+    /// and the send, so it's only approximate.
+    ///
+    /// ```
+    /// use readahead_iterator::IntoReadahead;
+    ///
+    /// for (depth, _item) in (0..10).readahead_with_depth(4) {
+    ///     assert!(depth <= 4);
+    /// }
+    /// ```
+    fn readahead_with_depth(self, buffer_size: usize) -> Readahead<(usize, T)>
+    where
+        Self: Send + 'static;
+
+    /// Read ahead, returning a [`ShutdownHandle`] that can stop the worker
+    /// from any thread.
+    ///
+    /// After [`ShutdownHandle::stop`], the worker produces no more items, but
+    /// those already produced are drained: `next()` returns them, and then
+    /// `None`. The item the worker was producing when it was stopped is also
+    /// delivered. Dropping the `Readahead` instead discards buffered items.
+    ///
+    /// ```
+    /// use readahead_iterator::IntoReadahead;
+    ///
+    /// let (mut rah, shutdown) = (0..).readahead_with_shutdown(4);
+    /// assert_eq!(rah.next(), Some(0));
+    /// shutdown.stop();
+    /// assert!(rah.count() <= 5);
+    /// ```
+    fn readahead_with_shutdown(self, buffer_size: usize) -> (Readahead<T>, ShutdownHandle)
+    where
+        Self: Send + 'static;
+
+    /// Read ahead, also folding each item into a summary on the worker, which
+    /// can be taken once the stream ends.
+    ///
+    /// `f` updates the summary, starting from `init`, with each item as the
+    /// worker produces it: for example to compute a checksum or total size of
+    /// everything read, without a second pass. The summary is only available
+    /// from [`ReadaheadWithSummary::take_summary`] after `next()` has
+    /// returned `None`.
+    ///
+    /// ```
+    /// use readahead_iterator::IntoReadahead;
+    ///
+    /// let mut rah = vec!["one", "three"]
+    ///     .into_iter()
+    ///     .readahead_with_summary(0, |total, s| *total += s.len(), 4);
+    /// assert_eq!(rah.take_summary(), None);
+    /// assert_eq!(rah.by_ref().count(), 2);
+    /// assert_eq!(rah.take_summary(), Some(8));
+    /// ```
+    fn readahead_with_summary<S, F>(
+        self,
+        init: S,
+        f: F,
+        buffer_size: usize,
+    ) -> ReadaheadWithSummary<T, S>
+    where
+        Self: Send + 'static,
+        S: Send + 'static,
+        F: FnMut(&mut S, &T) + Send + 'static;
+
+    /// Read ahead from the items not seen before, dropping any duplicates,
+    /// like `itertools::unique`.
+    ///
+    /// The worker keeps a clone of every distinct item in a `HashSet`, and
+    /// looks each item up there, overlapped with the consumer. Unlike
+    /// [`Iterator::filter`] alone, duplicates never cross the channel.
+    ///
+    /// **Caution:** The set grows for as long as distinct items keep coming,
+    /// so memory use is proportional to the number of distinct items. For a
+    /// stream with many distinct values, consider de-duplicating a key
+    /// instead.
+    ///
+    /// ```
+    /// use readahead_iterator::IntoReadahead;
+    ///
+    /// let v: Vec<u32> = vec![3, 1, 3, 2, 1].into_iter().readahead_distinct(4).collect();
+    /// assert_eq!(v, [3, 1, 2]);
+    /// ```
+    fn readahead_distinct(self, buffer_size: usize) -> Readahead<T>
+    where
+        Self: Send + 'static,
+        T: Eq + Hash + Clone;
+
+    /// Read ahead from this iterator and then `other`, each on its own worker
+    /// thread, as for [`Iterator::chain`].
+    ///
+    /// All of this iterator's items come before any of `other`'s. Both
+    /// workers start straight away, so while this iterator is being consumed,
+    /// the first `buffer_size` items of `other` are already being produced,
+    /// and there's no stall at the boundary. If both sources are slow to
+    /// start, this hides the second start-up behind the first source, which
+    /// `self.chain(other).readahead(n)` can't do.
+    ///
+    /// ```
+    /// use readahead_iterator::IntoReadahead;
+    ///
+    /// let v: Vec<u32> = (0..3).readahead_chain(10..12, 4).collect();
+    /// assert_eq!(v, [0, 1, 2, 10, 11]);
+    /// ```
+    fn readahead_chain<J>(self, other: J, buffer_size: usize) -> Chain<Readahead<T>, Readahead<T>>
+    where
+        Self: Send + 'static,
+        J: Iterator<Item = T> + Send + 'static;
+
+    /// Read ahead into a [`ReadaheadWindow`] that can borrow up to
+    /// `lookbehind` items before the current one, and `lookahead` items after
+    /// it.
+    ///
+    /// The worker's buffer also holds `lookahead` items, so looking ahead
+    /// usually finds them already produced. At the start of the stream there
+    /// are fewer items behind, and at the end fewer ahead: those accessors
+    /// return `None`.
+    ///
+    /// ```
+    /// use readahead_iterator::IntoReadahead;
+    ///
+    /// let mut window = "abcd".chars().readahead_window(1, 1);
+    /// let mut context = Vec::new();
+    /// while let Some(&current) = window.advance() {
+    ///     let before = window.behind(1).copied().unwrap_or('^');
+    ///     let after = window.ahead(1).copied().unwrap_or('$');
+    ///     context.push(format!("{}{}{}", before, current, after));
+    /// }
+    /// assert_eq!(context, ["^ab", "abc", "bcd", "cd$"]);
+    /// ```
+    fn readahead_window(self, lookbehind: usize, lookahead: usize) -> ReadaheadWindow<T>
+    where
+        Self: Send + 'static;
+
+    /// Read ahead on a worker thread, unless the iterator is known to be
+    /// shorter than `threshold`, in which case it runs inline.
+    ///
+    /// Spawning a thread costs more than reading a handful of items, so for
+    /// small sources readahead isn't worth it. When the upper bound of
+    /// [`Iterator::size_hint`] is less than `threshold`, no thread is spawned,
+    /// and the `Readahead` calls the iterator directly, as with
+    /// [`ReadaheadBuilder::enabled`]. Otherwise, including when there's no
+    /// upper bound, a worker is spawned as usual.
+    ///
+    /// The upper bound is used, rather than the lower, so that a source that
+    /// might be long, such as a filter over a large collection, is still read
+    /// ahead.
+    ///
+    /// ```
+    /// use readahead_iterator::IntoReadahead;
+    ///
+    /// let small: Vec<u32> = vec![1, 2, 3].into_iter().readahead_or_inline(8, 100).collect();
+    /// assert_eq!(small, [1, 2, 3]);
+    /// ```
+    fn readahead_or_inline(self, buffer_size: usize, threshold: usize) -> Readahead<T>
+    where
+        Self: Send + 'static;
+
+    /// Split the items into chunks of `chunk_size`, and map each chunk
+    /// through `f` on a pool of `workers` threads, delivering the mapped
+    /// chunks in the order of the source.
+    ///
+    /// This is coarse-grained parallelism: handing out whole chunks spreads
+    /// the cost of scheduling and reordering over many items, so it suits
+    /// cheap per-item work better than one task per item would.
+    ///
+    /// The source is shared by the workers: each takes the next chunk from it
+    /// in turn. The mapped chunks are put back in order on one more thread,
+    /// and up to `buffer_size` of them are buffered. At most
+    /// `workers + buffer_size` chunks are taken but not yet received by the
+    /// consumer, so a slow chunk holds up the rest rather than letting memory
+    /// use grow. The last chunk may be shorter than `chunk_size`.
+    ///
+    /// If `f` or the source panics, the panic is resumed from the consumer's
+    /// `next()`, possibly before some chunks that came earlier in the source.
+    ///
+    /// Panics if `chunk_size` or `workers` is zero.
+    ///
+    /// ```
+    /// use readahead_iterator::IntoReadahead;
+    ///
+    /// let squares: Vec<u64> = (0..1000u64)
+    ///     .readahead_map_parallel_chunks(64, 4, |chunk| chunk.iter().map(|i| i * i).collect(), 4)
+    ///     .flatten()
+    ///     .collect();
+    /// assert!(squares.iter().copied().eq((0..1000).map(|i| i * i)));
+    /// ```
+    fn readahead_map_parallel_chunks<U, F>(
+        self,
+        chunk_size: usize,
+        workers: usize,
+        f: F,
+        buffer_size: usize,
+    ) -> Readahead<Vec<U>>
+    where
+        Self: Send + 'static,
+        U: Send + 'static,
+        F: Fn(Vec<T>) -> Vec<U> + Send + Sync + 'static;
+
+    /// Read ahead on a worker thread named by `name_fn`, called with the first
+    /// item.
+    ///
+    /// This shows which readahead is doing what in a debugger or profiler, when
+    /// the first item says what the stream is about, such as the file it
+    /// comes from.
+    ///
+    /// The standard library can't rename a running thread, so the first item
+    /// is produced on a thread with the default name, and then a thread is
+    /// started with the chosen name to produce the rest. It holds a buffer of
+    /// up to `buffer_size` items. The first thread stays, to pass the items
+    /// on, so each one takes an extra handoff between threads: this is meant
+    /// for items that take some work to produce, where the name helps.
+    ///
+    /// If the source is empty, `name_fn` isn't called.
+    ///
+    /// ```
+    /// use std::thread;
+    /// use readahead_iterator::IntoReadahead;
+    ///
+    /// let names: Vec<String> = ["a.txt", "b.txt"]
+    ///     .iter()
+    ///     .map(|_| thread::current().name().unwrap().to_owned())
+    ///     .readahead_with_name_fn(4, |_| "reader".to_owned())
+    ///     .collect();
+    /// // The first item was produced before the name was known.
+    /// assert_eq!(names, ["readahead_iterator", "reader"]);
+    /// ```
+    fn readahead_with_name_fn<F>(self, buffer_size: usize, name_fn: F) -> Readahead<T>
+    where
+        Self: Send + 'static,
+        F: FnOnce(&T) -> String + Send + 'static;
+
+    /// Send a copy of every item to each of `n_consumers` readaheads, reading
+    /// the source once on a single worker thread.
+    ///
+    /// Each item is cloned for all but one of the outputs, so this suits items
+    /// that are cheap to clone, or are shared, such as an `Arc`.
+    ///
+    /// Each output has its own buffer of up to `buffer_size` items, and each
+    /// item is sent to every output before the worker reads the next. So the
+    /// slowest consumer sets the pace: once its buffer is full, the worker
+    /// waits for it, and the others can't get more than `buffer_size` items
+    /// ahead of it. The outputs should be consumed concurrently.
+    ///
+    /// If an output is dropped, it gets no more copies, and the worker goes on
+    /// feeding the others. The worker stops when they're all dropped. If the
+    /// inner iterator panics, the panic is resumed from the first output, and
+    /// the others panic with a generic message.
+    ///
+    /// Panics if `n_consumers` is zero.
+    ///
+    /// ```
+    /// use std::thread;
+    /// use readahead_iterator::IntoReadahead;
+    ///
+    /// let mut outputs = (0..100u64).readahead_fork(2, 8);
+    /// let squares = outputs.pop().unwrap();
+    /// let squares = thread::spawn(move || squares.map(|i| i * i).sum::<u64>());
+    /// let sum: u64 = outputs.pop().unwrap().sum();
+    /// assert_eq!(sum, 4950);
+    /// assert_eq!(squares.join().unwrap(), 328350);
+    /// ```
+    fn readahead_fork(self, n_consumers: usize, buffer_size: usize) -> Vec<Readahead<T>>
+    where
+        Self: Send + 'static,
+        T: Clone;
+
+    /// Skip leading items on the worker thread, as for
+    /// [`Iterator::skip_while`], and read ahead from the rest.
+    ///
+    /// Like [`readahead_filter`](IntoReadahead::readahead_filter), this makes
+    /// it explicit that skipped items are dropped on the worker and never
+    /// sent to the consumer. Once `predicate` returns false it isn't called
+    /// again, and every later item is sent.
+    ///
+    /// ```
+    /// use readahead_iterator::IntoReadahead;
+    ///
+    /// let v: Vec<&str> = "# header\n# more\nbody\n# not header"
+    ///     .lines()
+    ///     .readahead_skip_while(|line| line.starts_with('#'), 4)
+    ///     .collect();
+    /// assert_eq!(v, ["body", "# not header"]);
+    /// ```
+    fn readahead_skip_while<P>(self, predicate: P, buffer_size: usize) -> Readahead<T>
+    where
+        Self: Send + 'static,
+        P: FnMut(&T) -> bool + Send + 'static;
+
+    /// Read ahead, wrapping each item in an [`Arc`] on the worker thread.
+    ///
+    /// This is for items that will later be shared by several consumers, for
+    /// example with [`readahead_fork`](IntoReadahead::readahead_fork): cloning
+    /// an `Arc` is cheap however large the item is, and the item needn't be
+    /// `Clone`. The cost is an allocation per item, on the worker, and an
+    /// indirection on each access, which is only worth it when the item is
+    /// large or expensive to clone, or can't be cloned at all. Since the items
+    /// can then be reached from several threads at once, they must be `Sync`.
+    ///
+    /// ```
+    /// use readahead_iterator::IntoReadahead;
+    ///
+    /// let mut outputs = vec![vec![0u8; 1 << 20]; 4]
+    ///     .into_iter()
+    ///     .readahead_shared(2)
+    ///     .readahead_fork(2, 2);
+    /// let second = outputs.pop().unwrap();
+    /// let first = outputs.pop().unwrap();
+    /// // The two outputs share each buffer, rather than copying it.
+    /// for (a, b) in first.zip(second) {
+    ///     assert!(std::sync::Arc::ptr_eq(&a, &b));
+    /// }
+    /// ```
+    fn readahead_shared(self, buffer_size: usize) -> Readahead<Arc<T>>
+    where
+        Self: Send + 'static,
+        T: Sync;
+
+    /// Map each item together with its position in the source, on the worker
+    /// thread, reading ahead from the results.
+    ///
+    /// This is `.enumerate().map(...)`, with `f` run on the worker, so that
+    /// position-dependent work such as numbering rows overlaps with the
+    /// consumer. The index passed to `f` is the item's position in the source,
+    /// counting from zero, and increases by one for each item.
+    ///
+    /// ```
+    /// use readahead_iterator::IntoReadahead;
+    ///
+    /// let rows: Vec<String> = ["a", "b", "c"]
+    ///     .iter()
+    ///     .readahead_map_with_index(|i, s| format!("{}: {}", i + 1, s), 4)
+    ///     .collect();
+    /// assert_eq!(rows, ["1: a", "2: b", "3: c"]);
+    /// ```
+    fn readahead_map_with_index<U, F>(self, f: F, buffer_size: usize) -> Readahead<U>
+    where
+        Self: Send + 'static,
+        F: FnMut(usize, T) -> U + Send + 'static,
+        U: Send + 'static;
+
+    /// Read ahead, delivering the greatest buffered item first, rather than
+    /// the oldest.
+    ///
+    /// The buffer is a bounded priority queue of up to `buffer_size` items:
+    /// each call to `next()` takes everything the worker has sent so far, and
+    /// returns the greatest, by `Ord`, of the items buffered and not yet
+    /// delivered. This suits a consumer that prefetches candidate tasks, and
+    /// when it falls behind, wants to work on the most important one that's
+    /// ready. To order by a key, or least first, wrap the items, for example
+    /// in [`std::cmp::Reverse`].
+    ///
+    /// **Caution:** this gives up the ordering guarantee. The order depends
+    /// on how far ahead the worker has got each time: an item is only
+    /// compared with the others buffered at the same time, and an item
+    /// that's already been delivered isn't affected by greater ones that
+    /// come later. If the consumer keeps up with the worker, items come in
+    /// nearly source order.
+    ///
+    /// ```
+    /// use readahead_iterator::IntoReadahead;
+    ///
+    /// let mut tasks = vec![(1, "tidy"), (5, "deploy"), (3, "test")]
+    ///     .into_iter()
+    ///     .readahead_priority(8);
+    /// tasks.warm_up(3);
+    /// assert_eq!(tasks.next(), Some((5, "deploy")));
+    /// assert_eq!(tasks.next(), Some((3, "test")));
+    /// assert_eq!(tasks.next(), Some((1, "tidy")));
+    /// ```
+    fn readahead_priority(self, buffer_size: usize) -> Readahead<T>
+    where
+        Self: Send + 'static,
+        T: Ord;
+
+    /// Read ahead until `deadline`, then stop taking items from this
+    /// iterator.
+    ///
+    /// This is for time-boxed jobs, which should process as many items as
+    /// they can by a fixed time. Items made before the deadline, including
+    /// one that's already being made when it passes, are still delivered;
+    /// then the stream ends. See [`ReadaheadBuilder::deadline`], which also
+    /// allows a [`ReadaheadBuilder::clock`] to be given for tests.
+    ///
+    /// ```
+    /// use std::time::{Duration, Instant};
+    /// use readahead_iterator::IntoReadahead;
+    ///
+    /// let deadline = Instant::now() + Duration::from_secs(60);
+    /// let done = (0..10).readahead_time_bounded(deadline, 4).count();
+    /// assert_eq!(done, 10);
+    /// ```
+    fn readahead_time_bounded(self, deadline: Instant, buffer_size: usize) -> Readahead<T>
+    where
+        Self: Send + 'static;
+}
+
+impl<I, T> IntoReadahead<T> for I
+where
+    T: Send + 'static,
+    I: Iterator<Item = T>,
+{
+    fn readahead(self, buffer_size: usize) -> Readahead<T>
+    where
+        Self: Send + 'static,
+    {
+        Readahead::new(self, buffer_size)
+    }
+
+    fn readahead_cycle(self, buffer_size: usize) -> Readahead<T>
+    where
+        Self: Clone + Send + 'static,
+    {
+        Readahead::new(self.cycle(), buffer_size)
+    }
+
+    fn readahead_map_while<U, F>(self, f: F, buffer_size: usize) -> Readahead<U>
+    where
+        Self: Send + 'static,
+        F: FnMut(T) -> Option<U> + Send + 'static,
+        U: Send + 'static,
+    {
+        Readahead::new(self.map_while(f), buffer_size)
+    }
+
+    fn readahead_zip<J>(self, other: J, buffer_size: usize) -> ReadaheadZip<T, J::Item>
+    where
+        Self: Send + 'static,
+        J: Iterator + Send + 'static,
+        J::Item: Send + 'static,
+    {
+        ReadaheadZip::new(
+            Readahead::new(self, buffer_size),
+            Readahead::new(other, buffer_size),
+        )
+    }
+
+    fn readahead_scan<St, U, F>(self, init: St, f: F, buffer_size: usize) -> Readahead<U>
+    where
+        Self: Send + 'static,
+        St: Send + 'static,
+        F: FnMut(&mut St, T) -> Option<U> + Send + 'static,
+        U: Send + 'static,
+    {
+        Readahead::new(self.scan(init, f), buffer_size)
+    }
+
+    fn readahead_step_by(self, step: usize, buffer_size: usize) -> Readahead<T>
+    where
+        Self: Send + 'static,
+    {
+        Readahead::new(self.step_by(step), buffer_size)
+    }
+
+    fn readahead_filter<P>(self, predicate: P, buffer_size: usize) -> Readahead<T>
+    where
+        Self: Send + 'static,
+        P: FnMut(&T) -> bool + Send + 'static,
+    {
+        Readahead::new(self.filter(predicate), buffer_size)
+    }
+
+    fn readahead_inspect<F>(self, f: F, buffer_size: usize) -> Readahead<T>
+    where
+        Self: Send + 'static,
+        F: FnMut(&T) + Send + 'static,
+    {
+        Readahead::new(self.inspect(f), buffer_size)
+    }
+
+    fn try_readahead(self, buffer_size: usize) -> io::Result<Readahead<T>>
+    where
+        Self: Send + 'static,
+    {
+        Readahead::try_new(self, buffer_size)
+    }
+
+    fn readahead_take(self, n: usize, buffer_size: usize) -> Readahead<T>
+    where
+        Self: Send + 'static,
+    {
+        Readahead::new(self.take(n), buffer_size)
+    }
+
+    fn readahead_last(self) -> Readahead<T>
+    where
+        Self: Send + 'static,
+    {
+        Readahead::new(iter::once_with(move || self.last()).flatten(), 1)
+    }
+
+    fn readahead_peekable(self, buffer_size: usize) -> ReadaheadPeekable<T>
+    where
+        Self: Send + 'static,
+    {
+        ReadaheadPeekable::new(Readahead::new(self, buffer_size))
+    }
+
+    fn readahead_group_by<K, F>(self, key_fn: F, buffer_size: usize) -> Readahead<(K, Vec<T>)>
+    where
+        Self: Send + 'static,
+        F: FnMut(&T) -> K + Send + 'static,
+        K: PartialEq + Send + 'static,
+    {
+        Readahead::new(GroupBy::new(self, key_fn), buffer_size)
+    }
+
+    fn readahead_flat_map<U, F>(self, f: F, buffer_size: usize) -> Readahead<U::Item>
+    where
+        Self: Send + 'static,
+        F: FnMut(T) -> U + Send + 'static,
+        U: IntoIterator,
+        U::Item: Send + 'static,
+    {
+        Readahead::from_factory(move || self.flat_map(f), buffer_size)
+    }
+
+    fn readahead_partition<P>(
+        self,
+        predicate: P,
+        buffer_size: usize,
+    ) -> (Readahead<T>, Readahead<T>)
+    where
+        Self: Send + 'static,
+        P: FnMut(&T) -> bool + Send + 'static,
+    {
+        partition::partition(self, predicate, buffer_size)
+    }
+
+    fn readahead_with_context<Ctx, U, Init, F>(
+        self,
+        init: Init,
+        mut f: F,
+        buffer_size: usize,
+    ) -> Readahead<U>
+    where
+        Self: Send + 'static,
+        Init: FnOnce() -> Ctx + Send + 'static,
+        F: FnMut(&mut Ctx, T) -> U + Send + 'static,
+        U: Send + 'static,
+    {
+        Readahead::from_factory(
+            move || {
+                let mut ctx = init();
+                self.map(move |item| f(&mut ctx, item))
+            },
+            buffer_size,
+        )
+    }
+
+    fn readahead_sorted(self, buffer_size: usize) -> Readahead<T>
+    where
+        Self: Send + 'static,
+        T: Ord,
+    {
+        Readahead::new(Sorted::new(self, buffer_size), buffer_size)
+    }
+
+    fn readahead_windows(self, size: usize, buffer_size: usize) -> Readahead<Vec<T>>
+    where
+        Self: Send + 'static,
+        T: Clone,
+    {
+        Readahead::new(Windows::new(self, size), buffer_size)
+    }
+
+    fn readahead_chunks_exact<const N: usize>(
+        self,
+        buffer_size: usize,
+    ) -> ReadaheadChunksExact<T, N>
+    where
+        Self: Send + 'static,
+    {
+        ReadaheadChunksExact::new(Readahead::new(ChunksExact::new(self), buffer_size))
+    }
+
+    fn readahead_acked<F>(self, on_ack: F, buffer_size: usize) -> Readahead<Ack<T>>
+    where
+        Self: Send + 'static,
+        F: FnMut(usize) + Send + 'static,
+    {
+        Readahead::from_factory(move || Acked::new(self, on_ack), buffer_size)
+    }
+
+    fn readahead_count(self) -> Readahead<usize>
+    where
+        Self: Send + 'static,
+    {
+        Readahead::new(iter::once_with(move || self.count()), 1)
+    }
+
+    fn readahead_coalesce_time(
+        self,
+        window: Duration,
+        max: usize,
+        buffer_size: usize,
+    ) -> Readahead<Vec<T>>
+    where
+        Self: Send + 'static,
+    {
+        let batches = CoalesceTime::new(Readahead::new(self, max), window, max);
+        Readahead::new(batches, buffer_size)
+    }
+
+    fn readahead_with_depth(self, buffer_size: usize) -> Readahead<(usize, T)>
+    where
+        Self: Send + 'static,
+    {
+        ReadaheadBuilder::new(buffer_size)
+            .spawn_with_shared(move |shared| {
+                let shared = shared.clone();
+                // The count can briefly include an item the consumer has
+                // just received, so cap it at what the buffer can hold.
+                self.map(move |item| (shared.buffered().min(buffer_size), item))
+            })
+            .expect("failed to spawn readahead_iterator thread")
+    }
+
+    fn readahead_with_shutdown(self, buffer_size: usize) -> (Readahead<T>, ShutdownHandle)
+    where
+        Self: Send + 'static,
+    {
+        let (inner, handle) = UntilShutdown::new(self);
+        (Readahead::new(inner, buffer_size), handle)
+    }
+
+    fn readahead_with_summary<S, F>(
+        self,
+        init: S,
+        f: F,
+        buffer_size: usize,
+    ) -> ReadaheadWithSummary<T, S>
+    where
+        Self: Send + 'static,
+        S: Send + 'static,
+        F: FnMut(&mut S, &T) + Send + 'static,
+    {
+        let (inner, summary) = Summarize::new(self, init, f);
+        ReadaheadWithSummary::new(Readahead::new(inner, buffer_size), summary)
+    }
+
+    fn readahead_distinct(self, buffer_size: usize) -> Readahead<T>
+    where
+        Self: Send + 'static,
+        T: Eq + Hash + Clone,
+    {
+        let mut seen = HashSet::new();
+        // Only clone items that are new.
+        let distinct = self.filter(move |item| !seen.contains(item) && seen.insert(item.clone()));
+        Readahead::new(distinct, buffer_size)
+    }
+
+    fn readahead_chain<J>(self, other: J, buffer_size: usize) -> Chain<Readahead<T>, Readahead<T>>
+    where
+        Self: Send + 'static,
+        J: Iterator<Item = T> + Send + 'static,
+    {
+        Readahead::new(self, buffer_size).chain(Readahead::new(other, buffer_size))
+    }
+
+    fn readahead_window(self, lookbehind: usize, lookahead: usize) -> ReadaheadWindow<T>
+    where
+        Self: Send + 'static,
+    {
+        ReadaheadWindow::new(
+            Readahead::new(self, lookahead.max(1)),
+            lookbehind,
+            lookahead,
+        )
+    }
+
+    fn readahead_or_inline(self, buffer_size: usize, threshold: usize) -> Readahead<T>
+    where
+        Self: Send + 'static,
+    {
+        let small = matches!(self.size_hint().1, Some(len) if len < threshold);
+        ReadaheadBuilder::new(buffer_size)
+            .enabled(!small)
+            .spawn(self)
+            .expect("failed to spawn readahead_iterator thread")
+    }
+
+    fn readahead_map_parallel_chunks<U, F>(
+        self,
+        chunk_size: usize,
+        workers: usize,
+        f: F,
+        buffer_size: usize,
+    ) -> Readahead<Vec<U>>
+    where
+        Self: Send + 'static,
+        U: Send + 'static,
+        F: Fn(Vec<T>) -> Vec<U> + Send + Sync + 'static,
+    {
+        ReadaheadBuilder::new(buffer_size)
+            .spawn_map_parallel_chunks(self, chunk_size, workers, f)
+            .expect("failed to spawn readahead_iterator thread")
+    }
+
+    fn readahead_with_name_fn<F>(self, buffer_size: usize, name_fn: F) -> Readahead<T>
+    where
+        Self: Send + 'static,
+        F: FnOnce(&T) -> String + Send + 'static,
+    {
+        let mut source = self;
+        // The first thread only passes items on, so it needs no buffer of its
+        // own.
+        ReadaheadBuilder::new(0)
+            .spawn_with(move || {
+                let named = source.next().map(|first| {
+                    ReadaheadBuilder::new(buffer_size)
+                        .name(name_fn(&first))
+                        .spawn(iter::once(first).chain(source))
+                        .expect("failed to spawn readahead_iterator thread")
+                });
+                named.into_iter().flatten()
+            })
+            .expect("failed to spawn readahead_iterator thread")
+    }
+
+    fn readahead_fork(self, n_consumers: usize, buffer_size: usize) -> Vec<Readahead<T>>
+    where
+        Self: Send + 'static,
+        T: Clone,
+    {
+        partition::fork(self, n_consumers, buffer_size)
+    }
+
+    fn readahead_skip_while<P>(self, predicate: P, buffer_size: usize) -> Readahead<T>
+    where
+        Self: Send + 'static,
+        P: FnMut(&T) -> bool + Send + 'static,
+    {
+        Readahead::new(self.skip_while(predicate), buffer_size)
+    }
+
+    fn readahead_shared(self, buffer_size: usize) -> Readahead<Arc<T>>
+    where
+        Self: Send + 'static,
+        T: Sync,
+    {
+        Readahead::new(self.map(Arc::new), buffer_size)
+    }
+
+    fn readahead_map_with_index<U, F>(self, mut f: F, buffer_size: usize) -> Readahead<U>
+    where
+        Self: Send + 'static,
+        F: FnMut(usize, T) -> U + Send + 'static,
+        U: Send + 'static,
+    {
+        Readahead::new(
+            self.enumerate().map(move |(i, item)| f(i, item)),
+            buffer_size,
+        )
+    }
+
+    fn readahead_priority(self, buffer_size: usize) -> Readahead<T>
+    where
+        Self: Send + 'static,
+        T: Ord,
+    {
+        ReadaheadBuilder::new(buffer_size)
+            .priority()
+            .spawn(self)
+            .expect("failed to spawn readahead_iterator thread")
+    }
+
+    fn readahead_time_bounded(self, deadline: Instant, buffer_size: usize) -> Readahead<T>
+    where
+        Self: Send + 'static,
+    {
+        ReadaheadBuilder::new(buffer_size)
+            .deadline(deadline)
+            .spawn(self)
+            .expect("failed to spawn readahead_iterator thread")
     }
 }
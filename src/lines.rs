@@ -0,0 +1,67 @@
+// Copyright 2021 Martin Pool
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Read lines ahead from a `BufRead`.
+
+use std::io::{self, BufRead};
+
+use crate::Readahead;
+
+/// Read lines from `reader` on a worker thread, as for [`BufRead::lines`].
+///
+/// Each line is returned without its trailing `\n` or `\r\n`. After an error,
+/// no more lines are read.
+///
+/// Every line has to be a separate `String` so that it can be sent to the
+/// consumer, but each is allocated with the length of the previous line, so
+/// runs of similar-length lines don't repeatedly grow their buffers. More
+/// importantly, the reading and allocation happen on the worker thread,
+/// overlapped with the consumer's processing.
+///
+/// ```
+/// use std::io::Cursor;
+/// use readahead_iterator::readahead_lines;
+///
+/// let lines: Vec<String> = readahead_lines(Cursor::new("one\ntwo\r\nthree"), 10)
+///     .collect::<Result<_, _>>()
+///     .unwrap();
+/// assert_eq!(lines, ["one", "two", "three"]);
+/// ```
+pub fn readahead_lines<R>(mut reader: R, buffer_size: usize) -> Readahead<io::Result<String>>
+where
+    R: BufRead + Send + 'static,
+{
+    let mut last_len = 0;
+    let mut failed = false;
+    Readahead::from_generator(
+        move || {
+            if failed {
+                return None;
+            }
+            let mut line = String::with_capacity(last_len);
+            match reader.read_line(&mut line) {
+                Ok(0) => None,
+                Ok(_) => {
+                    last_len = line.len();
+                    if line.ends_with('\n') {
+                        line.pop();
+                        if line.ends_with('\r') {
+                            line.pop();
+                        }
+                    }
+                    Some(Ok(line))
+                }
+                Err(err) => {
+                    failed = true;
+                    Some(Err(err))
+                }
+            }
+        },
+        buffer_size,
+    )
+}
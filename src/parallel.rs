@@ -0,0 +1,208 @@
+// Copyright 2021 Martin Pool
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Mapping chunks of items on several worker threads, keeping their order.
+
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::io;
+use std::iter;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{sync_channel, Receiver};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+use crate::builder::DEFAULT_THREAD_NAME;
+use crate::{Readahead, ReadaheadBuilder};
+
+/// The result of mapping one chunk, or the panic that stopped a worker.
+type Mapped<U> = Result<Vec<U>, Box<dyn Any + Send>>;
+
+/// The source, and how far the workers may run ahead of the consumer.
+struct Dispatch<I> {
+    source: I,
+    /// The sequence number of the next chunk to be taken from the source.
+    next: usize,
+    /// The number of chunks delivered, in order, to the consumer.
+    delivered: usize,
+    /// True once the consumer is dropped, or the source is exhausted.
+    stopped: bool,
+}
+
+/// State shared by the mapping workers and the reordering worker.
+struct Shared<I> {
+    dispatch: Mutex<Dispatch<I>>,
+    /// Notified when a chunk is delivered, or the consumer is dropped.
+    changed: Condvar,
+    /// The most chunks that may be taken but not yet delivered.
+    in_flight: usize,
+}
+
+impl<I: Iterator> Shared<I> {
+    /// Take the next chunk and its sequence number, waiting while too many
+    /// chunks are in flight.
+    ///
+    /// Returns `None` once the source is exhausted or the consumer is gone.
+    fn take_chunk(&self, chunk_size: usize) -> Option<(usize, Vec<I::Item>)> {
+        let mut dispatch = self.dispatch.lock().unwrap();
+        loop {
+            if dispatch.stopped {
+                return None;
+            }
+            if dispatch.next - dispatch.delivered < self.in_flight {
+                break;
+            }
+            dispatch = self.changed.wait(dispatch).unwrap();
+        }
+        let chunk: Vec<I::Item> = dispatch.source.by_ref().take(chunk_size).collect();
+        if chunk.is_empty() {
+            dispatch.stopped = true;
+            self.changed.notify_all();
+            return None;
+        }
+        let seq = dispatch.next;
+        dispatch.next += 1;
+        Some((seq, chunk))
+    }
+}
+
+/// Runs on the reordering worker: receives mapped chunks as they finish, and
+/// delivers them in order.
+struct Reorder<I, U> {
+    shared: Arc<Shared<I>>,
+    results: Receiver<(usize, Mapped<U>)>,
+    /// Chunks that finished before some earlier chunk.
+    pending: BTreeMap<usize, Vec<U>>,
+    next: usize,
+}
+
+impl<I, U> Iterator for Reorder<I, U> {
+    type Item = Vec<U>;
+
+    fn next(&mut self) -> Option<Vec<U>> {
+        loop {
+            if let Some(mapped) = self.pending.remove(&self.next) {
+                self.next += 1;
+                self.shared.dispatch.lock().unwrap().delivered = self.next;
+                self.shared.changed.notify_all();
+                return Some(mapped);
+            }
+            // Every worker hangs up once there are no more chunks to take,
+            // and by then every chunk has been received.
+            match self.results.recv().ok()? {
+                (seq, Ok(mapped)) => {
+                    self.pending.insert(seq, mapped);
+                }
+                (_, Err(payload)) => panic::resume_unwind(payload),
+            }
+        }
+    }
+}
+
+impl<I, U> Drop for Reorder<I, U> {
+    fn drop(&mut self) {
+        // Release any workers waiting to take a chunk. If a worker panicked
+        // while holding the lock, it's poisoned, but the flag is still safe
+        // to set.
+        let mut dispatch = match self.shared.dispatch.lock() {
+            Ok(dispatch) => dispatch,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        dispatch.stopped = true;
+        self.shared.changed.notify_all();
+    }
+}
+
+/// Map chunks of `chunk_size` items from `source` through `f` on `workers`
+/// threads, delivering the mapped chunks in order through a readahead spawned
+/// by `builder`.
+///
+/// At most `in_flight` chunks are taken from the source but not yet
+/// delivered.
+pub(crate) fn map_parallel_chunks<I, U, F>(
+    source: I,
+    chunk_size: usize,
+    workers: usize,
+    f: F,
+    in_flight: usize,
+    builder: ReadaheadBuilder<Vec<U>>,
+) -> io::Result<Readahead<Vec<U>>>
+where
+    I: Iterator + Send + 'static,
+    I::Item: Send + 'static,
+    U: Send + 'static,
+    F: Fn(Vec<I::Item>) -> Vec<U> + Send + Sync + 'static,
+{
+    assert!(chunk_size > 0, "chunk_size must be greater than zero");
+    assert!(workers > 0, "workers must be greater than zero");
+    let shared = Arc::new(Shared {
+        dispatch: Mutex::new(Dispatch {
+            source,
+            next: 0,
+            delivered: 0,
+            stopped: false,
+        }),
+        changed: Condvar::new(),
+        in_flight,
+    });
+    let f = Arc::new(f);
+    let (result_sender, results) = sync_channel(workers);
+    // Built first so that, if spawning a worker fails, dropping it releases
+    // the workers already spawned.
+    let reorder = Reorder {
+        shared: shared.clone(),
+        results,
+        pending: BTreeMap::new(),
+        next: 0,
+    };
+    for _ in 0..workers {
+        let shared = shared.clone();
+        let f = f.clone();
+        let result_sender = result_sender.clone();
+        thread::Builder::new()
+            .name(DEFAULT_THREAD_NAME.to_owned())
+            .spawn(move || {
+                let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                    while let Some((seq, chunk)) = shared.take_chunk(chunk_size) {
+                        if result_sender.send((seq, Ok(f(chunk)))).is_err() {
+                            return;
+                        }
+                    }
+                }));
+                if let Err(payload) = result {
+                    // The chunk this worker was mapping will never arrive, so
+                    // the reordering worker would otherwise wait for it
+                    // forever.
+                    let _ = result_sender.send((usize::MAX, Err(payload)));
+                }
+            })?;
+    }
+    builder.spawn(reorder)
+}
+
+/// Map chunks of `chunk_size` items from `source` through `f`, one after
+/// another, on the thread that calls `next()`.
+pub(crate) fn map_chunks_inline<I, U, F>(
+    mut source: I,
+    chunk_size: usize,
+    f: F,
+) -> impl Iterator<Item = Vec<U>>
+where
+    I: Iterator,
+    F: Fn(Vec<I::Item>) -> Vec<U>,
+{
+    assert!(chunk_size > 0, "chunk_size must be greater than zero");
+    iter::from_fn(move || {
+        let chunk: Vec<I::Item> = source.by_ref().take(chunk_size).collect();
+        if chunk.is_empty() {
+            None
+        } else {
+            Some(f(chunk))
+        }
+    })
+}
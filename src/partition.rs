@@ -0,0 +1,242 @@
+// Copyright 2021 Martin Pool
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Splitting one source between several readaheads.
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{sync_channel, SyncSender};
+use std::sync::Arc;
+use std::thread;
+
+use crate::builder::DEFAULT_THREAD_NAME;
+use crate::shared::{DoneGuard, Shared};
+use crate::Readahead;
+
+/// One of the two outputs of a split.
+struct Side<T> {
+    /// `None` once the consumer of this side hangs up.
+    sender: Option<SyncSender<Option<T>>>,
+    shared: Arc<Shared>,
+}
+
+impl<T: Send + 'static> Side<T> {
+    /// Make a side feeding a new readahead with a buffer of `buffer_size`.
+    fn new(buffer_size: usize) -> (Side<T>, Readahead<T>) {
+        let (sender, receiver) = sync_channel(buffer_size);
+        let side = Side {
+            sender: Some(sender),
+            shared: Arc::new(Shared::default()),
+        };
+        let output = Readahead::from_parts(receiver, Some(side.shared.clone()));
+        (side, output)
+    }
+}
+
+impl<T> Side<T> {
+    fn send(&mut self, item: T) {
+        if let Some(sender) = &self.sender {
+            self.shared.will_send();
+            if sender.send(Some(item)).is_ok() {
+                self.shared.did_send();
+            } else {
+                self.shared.not_sent();
+                self.sender = None;
+            }
+        }
+    }
+
+    /// Called at the end of the source.
+    fn finish(&mut self) {
+        self.shared.set_source_ended();
+        if let Some(sender) = self.sender.take() {
+            let _ = sender.send(None);
+        }
+    }
+
+    fn is_open(&self) -> bool {
+        self.sender.is_some()
+    }
+}
+
+/// Spawn a worker that runs `body` to feed two new readaheads.
+///
+/// `body` should stop once both sides are closed. The end of the stream is
+/// sent to each side after `body` returns.
+fn split<A, B, F>(buffer_size: usize, body: F) -> (Readahead<A>, Readahead<B>)
+where
+    A: Send + 'static,
+    B: Send + 'static,
+    F: FnOnce(&mut Side<A>, &mut Side<B>) + Send + 'static,
+{
+    let (mut a, a_output) = Side::new(buffer_size);
+    let (mut b, b_output) = Side::new(buffer_size);
+    spawn_feeder(vec![a.shared.clone(), b.shared.clone()], move || {
+        body(&mut a, &mut b);
+        a.finish();
+        b.finish();
+    });
+    (a_output, b_output)
+}
+
+/// Spawn the worker thread for a split, which runs `feed` and marks each of
+/// the outputs sharing `shared` as done when it finishes.
+///
+/// If `feed` panics, the outputs all hang up, but the payload can only go to
+/// the first of them: the others get a generic message.
+fn spawn_feeder<F>(shared: Vec<Arc<Shared>>, feed: F)
+where
+    F: FnOnce() + Send + 'static,
+{
+    thread::Builder::new()
+        .name(DEFAULT_THREAD_NAME.to_owned())
+        .spawn(move || {
+            let _done_guards: Vec<DoneGuard> = shared.iter().cloned().map(DoneGuard).collect();
+            if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(feed)) {
+                let mut payload = Some(payload);
+                for shared in &shared {
+                    shared.set_panic(
+                        payload
+                            .take()
+                            .unwrap_or_else(|| Box::new("readahead_iterator worker panicked")),
+                    );
+                }
+            }
+        })
+        .expect("failed to spawn readahead_iterator thread");
+}
+
+/// Spawn a worker that sends items matching `predicate` to the first
+/// readahead, and the rest to the second.
+pub(crate) fn partition<I, P>(
+    inner: I,
+    mut predicate: P,
+    buffer_size: usize,
+) -> (Readahead<I::Item>, Readahead<I::Item>)
+where
+    I: Iterator + Send + 'static,
+    I::Item: Send + 'static,
+    P: FnMut(&I::Item) -> bool + Send + 'static,
+{
+    split(buffer_size, move |matching, rest| {
+        for item in inner {
+            if predicate(&item) {
+                matching.send(item);
+            } else {
+                rest.send(item);
+            }
+            if !matching.is_open() && !rest.is_open() {
+                return;
+            }
+        }
+    })
+}
+
+/// Spawn a worker that sends a copy of each item to each of `n` readaheads.
+pub(crate) fn fork<I>(inner: I, n: usize, buffer_size: usize) -> Vec<Readahead<I::Item>>
+where
+    I: Iterator + Send + 'static,
+    I::Item: Clone + Send + 'static,
+{
+    assert!(n > 0, "n_consumers must be greater than zero");
+    let (mut sides, outputs): (Vec<_>, Vec<_>) = (0..n).map(|_| Side::new(buffer_size)).unzip();
+    spawn_feeder(
+        sides.iter().map(|side| side.shared.clone()).collect(),
+        move || {
+            for item in inner {
+                // The last open side gets the original, so there's one clone fewer.
+                let last = match sides.iter().rposition(Side::is_open) {
+                    Some(last) => last,
+                    None => return,
+                };
+                for side in &mut sides[..last] {
+                    if side.is_open() {
+                        side.send(item.clone());
+                    }
+                }
+                sides[last].send(item);
+            }
+            for side in &mut sides {
+                side.finish();
+            }
+        },
+    );
+    outputs
+}
+
+/// Spawn a worker that sends the first half of each pair to the first
+/// readahead, and the second half to the second.
+fn unzip<I, A, B>(inner: I, buffer_size: usize) -> (Readahead<A>, Readahead<B>)
+where
+    I: Iterator<Item = (A, B)> + Send + 'static,
+    A: Send + 'static,
+    B: Send + 'static,
+{
+    split(buffer_size, move |a_side, b_side| {
+        for (a, b) in inner {
+            a_side.send(a);
+            b_side.send(b);
+            if !a_side.is_open() && !b_side.is_open() {
+                return;
+            }
+        }
+    })
+}
+
+/// Adds a readahead method for splitting pairs to any iterator of pairs.
+pub trait IntoReadaheadPairs<A, B>
+where
+    A: Send + 'static,
+    B: Send + 'static,
+{
+    /// Split pairs between two readaheads on a single worker thread, as for
+    /// [`Iterator::unzip`]: the first of each pair goes to the first output,
+    /// and the second to the second.
+    ///
+    /// The source is read once. Each output has its own buffer of up to
+    /// `buffer_size` items, and each pair is sent to both before the worker
+    /// reads the next. When one output's buffer is full, the worker waits for
+    /// it to be read, so a consumer of one output can't get more than
+    /// `buffer_size` items ahead of the other: the two outputs should be
+    /// consumed concurrently, or in lockstep.
+    ///
+    /// If one output is dropped, its halves are discarded and the worker keeps
+    /// feeding the other. The worker stops when both are dropped. If the inner
+    /// iterator panics, the panic is resumed from the first output, and the
+    /// second panics with a generic message.
+    ///
+    /// ```
+    /// use readahead_iterator::IntoReadaheadPairs;
+    ///
+    /// let (mut numbers, mut names) = vec![(1, "one"), (2, "two")]
+    ///     .into_iter()
+    ///     .readahead_unzip(4);
+    /// assert_eq!(numbers.next(), Some(1));
+    /// assert_eq!(names.next(), Some("one"));
+    /// assert_eq!(numbers.next(), Some(2));
+    /// assert_eq!(names.next(), Some("two"));
+    /// assert_eq!(numbers.next(), None);
+    /// assert_eq!(names.next(), None);
+    /// ```
+    fn readahead_unzip(self, buffer_size: usize) -> (Readahead<A>, Readahead<B>)
+    where
+        Self: Send + 'static;
+}
+
+impl<I, A, B> IntoReadaheadPairs<A, B> for I
+where
+    I: Iterator<Item = (A, B)>,
+    A: Send + 'static,
+    B: Send + 'static,
+{
+    fn readahead_unzip(self, buffer_size: usize) -> (Readahead<A>, Readahead<B>)
+    where
+        Self: Send + 'static,
+    {
+        unzip(self, buffer_size)
+    }
+}
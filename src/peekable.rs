@@ -0,0 +1,115 @@
+// Copyright 2021 Martin Pool
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A readahead iterator that can also peek at the next item.
+
+use crate::Readahead;
+
+/// A [`Readahead`] that can look at the next item without consuming it, like
+/// [`std::iter::Peekable`].
+///
+/// Created by [`IntoReadahead::readahead_peekable`](crate::IntoReadahead::readahead_peekable).
+///
+/// Peeking takes the next item from the buffer and holds it, so `peek()` is
+/// O(1), and the worker carries on reading ahead as usual. The end of the
+/// stream behaves the same as for [`Readahead`].
+///
+/// ```
+/// use readahead_iterator::IntoReadahead;
+///
+/// let mut tokens = "12+34".chars().readahead_peekable(8);
+/// let mut number = String::new();
+/// while let Some(c) = tokens.next_if(|c| c.is_ascii_digit()) {
+///     number.push(c);
+/// }
+/// assert_eq!(number, "12");
+/// assert_eq!(tokens.peek(), Some(&'+'));
+/// assert_eq!(tokens.next_if_eq(&'+'), Some('+'));
+/// assert_eq!(tokens.collect::<String>(), "34");
+/// ```
+pub struct ReadaheadPeekable<T: Send + 'static> {
+    inner: Readahead<T>,
+    /// `Some` if an item, or the end of the stream, has been peeked.
+    peeked: Option<Option<T>>,
+}
+
+impl<T> ReadaheadPeekable<T>
+where
+    T: Send + 'static,
+{
+    pub(crate) fn new(inner: Readahead<T>) -> Self {
+        ReadaheadPeekable {
+            inner,
+            peeked: None,
+        }
+    }
+
+    /// Borrow the next item without consuming it, blocking until it's
+    /// available.
+    ///
+    /// Returns `None` at the end of the stream.
+    pub fn peek(&mut self) -> Option<&T> {
+        let inner = &mut self.inner;
+        self.peeked.get_or_insert_with(|| inner.next()).as_ref()
+    }
+
+    /// Mutably borrow the next item without consuming it, blocking until it's
+    /// available.
+    ///
+    /// Returns `None` at the end of the stream.
+    pub fn peek_mut(&mut self) -> Option<&mut T> {
+        let inner = &mut self.inner;
+        self.peeked.get_or_insert_with(|| inner.next()).as_mut()
+    }
+
+    /// Consume and return the next item if `func` returns true for it.
+    ///
+    /// Otherwise, the item stays available to be peeked or consumed.
+    pub fn next_if(&mut self, func: impl FnOnce(&T) -> bool) -> Option<T> {
+        match self.next() {
+            Some(item) if func(&item) => Some(item),
+            other => {
+                self.peeked = Some(other);
+                None
+            }
+        }
+    }
+
+    /// Consume and return the next item if it's equal to `expected`.
+    pub fn next_if_eq<U>(&mut self, expected: &U) -> Option<T>
+    where
+        U: ?Sized,
+        T: PartialEq<U>,
+    {
+        self.next_if(|item| item == expected)
+    }
+
+    /// Cheaply guess whether there may be more items, without blocking.
+    ///
+    /// See [`Readahead::has_more`]; a peeked item counts as more.
+    pub fn has_more(&self) -> bool {
+        match &self.peeked {
+            Some(peeked) => peeked.is_some(),
+            None => self.inner.has_more(),
+        }
+    }
+}
+
+impl<T> Iterator for ReadaheadPeekable<T>
+where
+    T: Send + 'static,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        match self.peeked.take() {
+            Some(peeked) => peeked,
+            None => self.inner.next(),
+        }
+    }
+}
@@ -0,0 +1,47 @@
+// Copyright 2021 Martin Pool
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Multi-stage pipelines.
+
+/// Build a pipeline of stages, each running on its own thread, connected by
+/// bounded buffers.
+///
+/// The first argument is the source iterator. It's followed by one or more
+/// stages, each written as `(buffer_size, closure)`. Each stage maps items
+/// from the previous stage through its closure on a new worker thread, and
+/// buffers up to `buffer_size` of its results.
+///
+/// The result is a [`Readahead`](crate::Readahead) of the last stage's
+/// outputs, in the same order as the source.
+///
+/// This is equivalent to, and no more efficient than, chaining
+/// `.map(closure).readahead(buffer_size)` for each stage, but it keeps the
+/// stages readable. As with `readahead`, the source, the closures, and all
+/// the intermediate items must be `Send + 'static`.
+///
+/// ```
+/// use readahead_iterator::readahead_pipeline;
+///
+/// let lengths: Vec<usize> = readahead_pipeline!(
+///     vec!["a", "bb", "ccc"].into_iter(),
+///     (4, |s: &str| s.repeat(2)),
+///     (4, |s: String| s.len()),
+/// )
+/// .collect();
+/// assert_eq!(lengths, [2, 4, 6]);
+/// ```
+#[macro_export]
+macro_rules! readahead_pipeline {
+    ($source:expr $(, ($buffer_size:expr, $stage:expr))+ $(,)?) => {{
+        let iter = $source;
+        $(
+            let iter = $crate::Readahead::new(::std::iter::Iterator::map(iter, $stage), $buffer_size);
+        )+
+        iter
+    }};
+}
@@ -0,0 +1,286 @@
+// Copyright 2021 Martin Pool
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A bounded pool of threads shared by many readaheads.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{sync_channel, SyncSender, TrySendError};
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::thread;
+
+use crate::builder::DEFAULT_THREAD_NAME;
+use crate::shared::{DoneGuard, Shared};
+use crate::Readahead;
+
+/// Runs the producers of many readaheads on a fixed number of threads.
+///
+/// Each [`ReadaheadPool::readahead`] call adds a producer to the pool, rather
+/// than spawning a thread. Producers are run in steps, each of which pulls
+/// from the inner iterator until the buffer is full, so a producer waiting for
+/// its consumer doesn't hold a thread. When the pool is saturated, producers
+/// queue for a thread.
+///
+/// If a consumer finds its buffer empty and its producer isn't running, the
+/// consumer runs the producer itself until an item is available. This means a
+/// pipeline of any depth makes progress even on a pool with one thread: for
+/// example, a stage reading from another pooled stage helps run that stage
+/// when it's starved.
+///
+/// Threads are started as needed, up to `max_threads`, and stop once the pool
+/// is dropped and they've run everything queued. Readaheads that outlive the
+/// pool keep working, with their producers run only by their consumers.
+///
+/// A panic in an inner iterator is resumed on the consumer, as for
+/// [`PanicPolicy::Propagate`](crate::PanicPolicy::Propagate).
+///
+/// ```
+/// use readahead_iterator::ReadaheadPool;
+///
+/// let pool = ReadaheadPool::new(2);
+/// let mut stage = pool.readahead(0..100u32, 4);
+/// for _ in 0..4 {
+///     stage = pool.readahead(stage.map(|i| i + 1), 4);
+/// }
+/// assert_eq!(stage.sum::<u32>(), (4..104).sum());
+/// ```
+#[derive(Debug)]
+pub struct ReadaheadPool {
+    inner: Arc<PoolInner>,
+}
+
+#[derive(Debug)]
+struct PoolInner {
+    max_threads: usize,
+    state: Mutex<PoolState>,
+    /// Notified when a task is queued or the pool shuts down.
+    work: Condvar,
+}
+
+struct PoolState {
+    queue: VecDeque<Arc<dyn PoolTask>>,
+    threads: usize,
+    idle: usize,
+    shutdown: bool,
+}
+
+/// A producer that can be run a step at a time, on the pool or by its
+/// consumer.
+pub(crate) trait PoolTask: Send + Sync {
+    /// Produce items until the buffer is full or the stream ends.
+    fn step(&self);
+
+    /// Queue a step on the pool, unless one is already queued.
+    fn wake(self: Arc<Self>);
+
+    /// Run a queued step.
+    fn run(&self);
+}
+
+impl ReadaheadPool {
+    /// Create a pool that runs producers on up to `max_threads` threads.
+    ///
+    /// Panics if `max_threads` is zero.
+    pub fn new(max_threads: usize) -> Self {
+        assert!(max_threads > 0, "ReadaheadPool needs at least one thread");
+        ReadaheadPool {
+            inner: Arc::new(PoolInner {
+                max_threads,
+                state: Mutex::new(PoolState {
+                    queue: VecDeque::new(),
+                    threads: 0,
+                    idle: 0,
+                    shutdown: false,
+                }),
+                work: Condvar::new(),
+            }),
+        }
+    }
+
+    /// Read ahead from `inner`, producing items on the pool.
+    ///
+    /// `buffer_size` is the maximum number of buffered items. Since producers
+    /// never block waiting for the consumer, a `buffer_size` of zero is
+    /// treated as one.
+    pub fn readahead<I>(&self, inner: I, buffer_size: usize) -> Readahead<I::Item>
+    where
+        I: Iterator + Send + 'static,
+        I::Item: Send + 'static,
+    {
+        let (sender, receiver) = sync_channel(buffer_size.max(1));
+        let shared = Arc::new(Shared::default());
+        let stage = Arc::new(Stage {
+            state: Mutex::new(Some(StageState {
+                inner: Box::new(inner),
+                sender,
+                pending: None,
+                _done_guard: DoneGuard(shared.clone()),
+            })),
+            shared: shared.clone(),
+            queued: AtomicBool::new(false),
+            pool: Arc::downgrade(&self.inner),
+        });
+        stage.clone().wake();
+        let mut readahead = Readahead::from_parts(receiver, Some(shared));
+        readahead.buffer_size = Some(buffer_size.max(1));
+        readahead.pool_task = Some(stage);
+        readahead
+    }
+}
+
+impl Drop for ReadaheadPool {
+    fn drop(&mut self) {
+        self.inner.state.lock().unwrap().shutdown = true;
+        self.inner.work.notify_all();
+    }
+}
+
+impl PoolInner {
+    fn submit(self: &Arc<Self>, task: Arc<dyn PoolTask>) {
+        let mut state = self.state.lock().unwrap();
+        if state.shutdown {
+            return;
+        }
+        state.queue.push_back(task);
+        if state.idle == 0 && state.threads < self.max_threads {
+            let pool = self.clone();
+            let spawned = thread::Builder::new()
+                .name(format!("{}-pool", DEFAULT_THREAD_NAME))
+                .spawn(move || pool.work());
+            // If the thread can't be spawned, the task is still run by its
+            // consumer, or by another pool thread.
+            if spawned.is_ok() {
+                state.threads += 1;
+            }
+        }
+        self.work.notify_one();
+    }
+
+    /// Body of a pool thread.
+    fn work(&self) {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(task) = state.queue.pop_front() {
+                drop(state);
+                task.run();
+                state = self.state.lock().unwrap();
+            } else if state.shutdown {
+                break;
+            } else {
+                state.idle += 1;
+                state = self.work.wait(state).unwrap();
+                state.idle -= 1;
+            }
+        }
+        state.threads -= 1;
+    }
+}
+
+impl fmt::Debug for PoolState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PoolState")
+            .field("queued", &self.queue.len())
+            .field("threads", &self.threads)
+            .field("idle", &self.idle)
+            .field("shutdown", &self.shutdown)
+            .finish()
+    }
+}
+
+/// A producer run on a pool.
+struct Stage<T> {
+    /// `None` once the stream has ended, or the consumer hung up.
+    state: Mutex<Option<StageState<T>>>,
+    shared: Arc<Shared>,
+    /// True while a step is queued on the pool.
+    queued: AtomicBool,
+    pool: Weak<PoolInner>,
+}
+
+struct StageState<T> {
+    inner: Box<dyn Iterator<Item = T> + Send>,
+    sender: SyncSender<Option<T>>,
+    /// An item, or the end of the stream, that didn't fit in the buffer.
+    pending: Option<Option<T>>,
+    /// Marks the stage done when the state is dropped.
+    _done_guard: DoneGuard,
+}
+
+impl<T> StageState<T> {
+    /// Send items until the buffer is full, returning true, or until the
+    /// stream ends or the consumer hangs up, returning false.
+    fn send_until_full(&mut self, shared: &Shared) -> bool {
+        loop {
+            let item = match self.pending.take() {
+                Some(item) => item,
+                None => {
+                    let item = self.inner.next();
+                    if item.is_none() {
+                        shared.set_source_ended();
+                    }
+                    item
+                }
+            };
+            let end = item.is_none();
+            if !end {
+                shared.will_send();
+            }
+            match self.sender.try_send(item) {
+                Ok(()) if end => return false,
+                Ok(()) => shared.did_send(),
+                Err(TrySendError::Full(item)) => {
+                    if !end {
+                        shared.not_sent();
+                    }
+                    self.pending = Some(item);
+                    return true;
+                }
+                Err(TrySendError::Disconnected(_)) => return false,
+            }
+        }
+    }
+}
+
+impl<T: Send + 'static> PoolTask for Stage<T> {
+    fn step(&self) {
+        let mut state = self.state.lock().unwrap();
+        let running = match &mut *state {
+            Some(running) => running,
+            None => return,
+        };
+        match panic::catch_unwind(AssertUnwindSafe(|| running.send_until_full(&self.shared))) {
+            Ok(true) => (),
+            Ok(false) => *state = None,
+            Err(payload) => {
+                // Hang up without sending the end of the stream, so the
+                // consumer resumes the panic.
+                self.shared.set_panic(payload);
+                *state = None;
+            }
+        }
+    }
+
+    fn wake(self: Arc<Self>) {
+        if self.shared.is_done() {
+            return;
+        }
+        if !self.queued.swap(true, Ordering::SeqCst) {
+            match self.pool.upgrade() {
+                Some(pool) => pool.submit(self),
+                None => self.queued.store(false, Ordering::SeqCst),
+            }
+        }
+    }
+
+    fn run(&self) {
+        self.queued.store(false, Ordering::SeqCst);
+        self.step();
+    }
+}
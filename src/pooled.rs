@@ -0,0 +1,140 @@
+// Copyright 2021 Martin Pool
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Items whose storage is recycled from the consumer back to the worker.
+
+use std::ops::{Deref, DerefMut};
+use std::sync::mpsc::{channel, Sender};
+
+use crate::Readahead;
+
+/// Produce items on a worker thread by calling `fill` on storage recycled
+/// from a pool of up to `pool_size` items.
+///
+/// This is for items that are expensive to allocate, such as large buffers,
+/// and are finished with soon after they're received. Each item is delivered
+/// as a [`Pooled`], which returns it to the worker when dropped, to be filled
+/// again. Until the pool has `pool_size` items, new ones are made with
+/// `T::default()`; after that, nothing more is allocated.
+///
+/// `fill` is called on the worker thread with an item to fill in, either
+/// new or returned by the consumer, still holding whatever it held before.
+/// It returns false at the end of the stream.
+///
+/// The worker can't get ahead when all the items are in use, so `pool_size`
+/// should be larger than `buffer_size` plus the number of items the consumer
+/// holds at once. If the consumer keeps them all, the worker waits for one to
+/// be dropped, even after the `Readahead` itself is dropped.
+///
+/// Panics if `pool_size` is zero.
+///
+/// ```
+/// use readahead_iterator::readahead_pooled;
+///
+/// let mut n = 0;
+/// let rows = readahead_pooled(
+///     4,
+///     move |row: &mut Vec<u32>| {
+///         n += 1;
+///         row.clear();
+///         row.extend(0..n);
+///         n <= 10
+///     },
+///     2,
+/// );
+/// let total: u32 = rows.map(|row| row.iter().sum::<u32>()).sum();
+/// assert_eq!(total, 165);
+/// ```
+pub fn readahead_pooled<T, F>(
+    pool_size: usize,
+    mut fill: F,
+    buffer_size: usize,
+) -> Readahead<Pooled<T>>
+where
+    T: Default + Send + 'static,
+    F: FnMut(&mut T) -> bool + Send + 'static,
+{
+    assert!(pool_size > 0, "pool_size must be greater than zero");
+    let (recycle, recycled) = channel::<Option<T>>();
+    let mut allocated = 0;
+    Readahead::from_generator(
+        move || {
+            let mut item = loop {
+                match recycled.try_recv() {
+                    Ok(Some(item)) => break item,
+                    // An item was taken out of the pool, making room for
+                    // another.
+                    Ok(None) => allocated -= 1,
+                    Err(_) if allocated < pool_size => {
+                        allocated += 1;
+                        break T::default();
+                    }
+                    // Every item is in use: wait for one to come back.
+                    Err(_) => match recycled.recv().ok()? {
+                        Some(item) => break item,
+                        None => allocated -= 1,
+                    },
+                }
+            };
+            if fill(&mut item) {
+                Some(Pooled {
+                    item: Some(item),
+                    recycle: recycle.clone(),
+                })
+            } else {
+                None
+            }
+        },
+        buffer_size,
+    )
+}
+
+/// An item from [`readahead_pooled`], which goes back to the pool when
+/// dropped.
+///
+/// It derefs to the item.
+#[derive(Debug)]
+pub struct Pooled<T> {
+    /// `None` only once it's been taken out of the pool.
+    item: Option<T>,
+    recycle: Sender<Option<T>>,
+}
+
+impl<T> Pooled<T> {
+    /// Keep the item, rather than returning it to the pool.
+    ///
+    /// The worker makes a new item to take its place.
+    pub fn into_inner(mut self) -> T {
+        let item = self.item.take().unwrap();
+        let _ = self.recycle.send(None);
+        item
+    }
+}
+
+impl<T> Deref for Pooled<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.item.as_ref().unwrap()
+    }
+}
+
+impl<T> DerefMut for Pooled<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.item.as_mut().unwrap()
+    }
+}
+
+impl<T> Drop for Pooled<T> {
+    fn drop(&mut self) {
+        if let Some(item) = self.item.take() {
+            // If the worker has finished, the item is just dropped.
+            let _ = self.recycle.send(Some(item));
+        }
+    }
+}
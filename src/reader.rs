@@ -0,0 +1,151 @@
+// Copyright 2021 Martin Pool
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Read bytes ahead from a `Read`, into recycled buffers.
+
+use std::io::{self, BufRead, ErrorKind, Read};
+use std::mem;
+use std::sync::mpsc::{channel, Sender};
+
+use crate::Readahead;
+
+/// Read from `inner` on a worker thread, into a pool of `num_buffers` buffers
+/// of `buf_capacity` bytes each.
+///
+/// The worker fills buffers while the consumer reads from earlier ones, through
+/// the returned [`ReadaheadReader`], which implements [`BufRead`]. Once the
+/// consumer has read all of a buffer it's returned to the worker to be filled
+/// again, so after the first `num_buffers` reads nothing more is allocated.
+///
+/// Each buffer holds the bytes from one `read` call on `inner`, which may be
+/// fewer than `buf_capacity`. An error from `inner` is returned by the reader,
+/// after the bytes read before it, and then the reader is at the end of the
+/// stream.
+///
+/// To decompress on the worker as well, pass in the source already wrapped
+/// in a decoder, such as `flate2::read::GzDecoder` or
+/// `zstd::stream::read::Decoder`: the decoding then happens inside the
+/// worker's calls to `read`, overlapping with whatever the consumer does
+/// with the decompressed bytes, and a decoding error is returned by the
+/// reader like any other error from `inner`.
+///
+/// Panics if `buf_capacity` or `num_buffers` is zero.
+///
+/// ```
+/// use std::io::{BufRead, Cursor};
+/// use readahead_iterator::readahead_buffered_reader;
+///
+/// let text = "one\ntwo\nthree\n".repeat(100);
+/// let reader = readahead_buffered_reader(Cursor::new(text.clone()), 16, 4);
+/// let lines: Vec<String> = reader.lines().collect::<Result<_, _>>().unwrap();
+/// assert_eq!(lines.len(), 300);
+/// assert_eq!(lines[299], "three");
+/// ```
+pub fn readahead_buffered_reader<R>(
+    mut inner: R,
+    buf_capacity: usize,
+    num_buffers: usize,
+) -> ReadaheadReader
+where
+    R: Read + Send + 'static,
+{
+    assert!(buf_capacity > 0, "buf_capacity must be greater than zero");
+    assert!(num_buffers > 0, "num_buffers must be greater than zero");
+    let (recycle, recycled) = channel::<Vec<u8>>();
+    let mut allocated = 0;
+    let mut failed = false;
+    let filled = Readahead::from_generator(
+        move || {
+            if failed {
+                return None;
+            }
+            let mut buf = match recycled.try_recv() {
+                Ok(buf) => buf,
+                Err(_) if allocated < num_buffers => {
+                    allocated += 1;
+                    Vec::with_capacity(buf_capacity)
+                }
+                // Every buffer is waiting to be read: wait for one to come
+                // back. If the reader was dropped, stop.
+                Err(_) => recycled.recv().ok()?,
+            };
+            buf.resize(buf_capacity, 0);
+            loop {
+                match inner.read(&mut buf) {
+                    Ok(0) => return None,
+                    Ok(len) => {
+                        buf.truncate(len);
+                        return Some(Ok(buf));
+                    }
+                    Err(err) if err.kind() == ErrorKind::Interrupted => (),
+                    Err(err) => {
+                        failed = true;
+                        return Some(Err(err));
+                    }
+                }
+            }
+        },
+        num_buffers,
+    );
+    ReadaheadReader {
+        filled,
+        recycle,
+        current: Vec::new(),
+        pos: 0,
+    }
+}
+
+/// Reads bytes filled ahead by a worker thread.
+///
+/// Created by [`readahead_buffered_reader`].
+#[derive(Debug)]
+pub struct ReadaheadReader {
+    filled: Readahead<io::Result<Vec<u8>>>,
+    /// Returns buffers to the worker once they've been read.
+    recycle: Sender<Vec<u8>>,
+    /// The buffer being read.
+    current: Vec<u8>,
+    /// The number of bytes of `current` already read.
+    pos: usize,
+}
+
+impl BufRead for ReadaheadReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos == self.current.len() {
+            // Give the used buffer back before waiting, so that the worker
+            // always has one to fill.
+            let used = mem::take(&mut self.current);
+            if used.capacity() > 0 {
+                // The worker may have finished, in which case the buffer is
+                // just dropped.
+                let _ = self.recycle.send(used);
+            }
+            self.pos = 0;
+            match self.filled.next() {
+                Some(Ok(buf)) => self.current = buf,
+                Some(Err(err)) => return Err(err),
+                None => (),
+            }
+        }
+        Ok(&self.current[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.current.len());
+    }
+}
+
+impl Read for ReadaheadReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
@@ -0,0 +1,189 @@
+// Copyright 2021 Martin Pool
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A process-wide count of live workers, with an optional soft limit.
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use crate::shared::Shared;
+
+/// Number of workers running their inner iterator.
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+
+/// The soft limit on `LIVE`, or `usize::MAX` for none.
+static SOFT_LIMIT: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+/// True to make workers over the limit wait for others to finish.
+static QUEUE: AtomicBool = AtomicBool::new(false);
+
+/// True once the limit has been exceeded and reported, until the count
+/// falls back to the limit.
+static WARNED: AtomicBool = AtomicBool::new(false);
+
+/// Told the count and the limit when the limit is exceeded.
+static ON_OVER_LIMIT: Mutex<Option<OverLimitHandler>> = Mutex::new(None);
+
+/// Called with the number of live workers, and the soft limit they're over.
+type OverLimitHandler = Arc<dyn Fn(usize, usize) + Send + Sync>;
+
+/// Held while notifying `FINISHED`. It protects no data.
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// Notified when a worker finishes, or the settings change.
+static FINISHED: Condvar = Condvar::new();
+
+/// How often a queued worker checks whether its consumer has hung up.
+const QUEUE_POLL: Duration = Duration::from_millis(100);
+
+/// Counts the workers alive across the whole process, to catch an application
+/// accidentally starting enough of them to oversubscribe the CPU.
+///
+/// This is only available with the `registry` feature.
+///
+/// Every worker thread spawned for a [`Readahead`](crate::Readahead), by
+/// [`Readahead::new`](crate::Readahead::new), a
+/// [`ReadaheadBuilder`](crate::ReadaheadBuilder) or an
+/// [`IntoReadahead`](crate::IntoReadahead) method, is counted from when it
+/// starts until it finishes, including when it panics. Helper threads, such
+/// as those of a parallel map, and tasks on a
+/// [`ReadaheadPool`](crate::ReadaheadPool) aren't counted.
+///
+/// By default there's no limit. With a soft limit set, a worker that starts
+/// while the limit is already reached calls the handler given to
+/// [`on_over_limit`](ReadaheadRegistry::on_over_limit), if any, once, until
+/// the count falls back to the limit. If
+/// [queueing](ReadaheadRegistry::set_queue_over_limit) is also on, the
+/// worker instead waits to start its inner iterator until another finishes.
+///
+/// ```
+/// use readahead_iterator::{Readahead, ReadaheadRegistry};
+///
+/// ReadaheadRegistry::set_soft_limit(64);
+/// ReadaheadRegistry::on_over_limit(|live, limit| {
+///     eprintln!("{} readahead workers are alive, over {}", live, limit)
+/// });
+/// let r = Readahead::new(0..10, 2);
+/// assert!(ReadaheadRegistry::live_workers() <= 1);
+/// assert_eq!(r.count(), 10);
+/// ```
+#[derive(Debug)]
+pub struct ReadaheadRegistry {
+    _private: (),
+}
+
+impl ReadaheadRegistry {
+    /// The number of workers alive now.
+    pub fn live_workers() -> usize {
+        LIVE.load(Ordering::SeqCst)
+    }
+
+    /// Report when more than `n` workers are alive at once, or, if queueing is
+    /// on, have workers beyond `n` wait.
+    ///
+    /// Use `usize::MAX` to remove the limit.
+    pub fn set_soft_limit(n: usize) {
+        SOFT_LIMIT.store(n, Ordering::SeqCst);
+        wake_queued();
+    }
+
+    /// The soft limit, or `usize::MAX` if there's none.
+    pub fn soft_limit() -> usize {
+        SOFT_LIMIT.load(Ordering::SeqCst)
+    }
+
+    /// Call `handler` when a worker starts over the soft limit, with the
+    /// number of live workers and the limit.
+    ///
+    /// It's called on the new worker's thread, once each time the count goes
+    /// over the limit, and decides what to do about it, such as logging a
+    /// warning. This replaces any handler set before. Without one, going over
+    /// the limit isn't reported.
+    pub fn on_over_limit<F>(handler: F)
+    where
+        F: Fn(usize, usize) + Send + Sync + 'static,
+    {
+        *ON_OVER_LIMIT
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(Arc::new(handler));
+    }
+
+    /// Have workers that start over the soft limit wait for others to finish,
+    /// rather than only reporting it.
+    ///
+    /// A queued worker's consumer blocks in `next()` until the worker gets to
+    /// start. That deadlocks if the workers holding the slots are themselves
+    /// waiting for it, for example when the consumer of one readahead is
+    /// producing the source of another, so only turn this on when the
+    /// readaheads are independent.
+    ///
+    /// It's off by default.
+    pub fn set_queue_over_limit(queue: bool) {
+        QUEUE.store(queue, Ordering::SeqCst);
+        wake_queued();
+    }
+}
+
+/// Wake workers queued for a slot, to check again.
+fn wake_queued() {
+    let _guard = LOCK.lock();
+    FINISHED.notify_all();
+}
+
+/// Counts a worker as live while it exists.
+pub(crate) struct Registration(());
+
+impl Registration {
+    /// Called by a worker when it starts.
+    ///
+    /// If queueing over the limit, waits for a slot, unless the consumer
+    /// hangs up first.
+    pub(crate) fn new(shared: &Shared) -> Registration {
+        let live = if QUEUE.load(Ordering::SeqCst) {
+            // Take the slot while holding the lock, so that two queued
+            // workers can't both see the same free slot.
+            let mut guard = LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            while QUEUE.load(Ordering::SeqCst)
+                && LIVE.load(Ordering::SeqCst) >= SOFT_LIMIT.load(Ordering::SeqCst)
+                && !shared.is_hung_up()
+            {
+                guard = FINISHED
+                    .wait_timeout(guard, QUEUE_POLL)
+                    .unwrap_or_else(|poisoned| poisoned.into_inner())
+                    .0;
+            }
+            LIVE.fetch_add(1, Ordering::SeqCst) + 1
+        } else {
+            LIVE.fetch_add(1, Ordering::SeqCst) + 1
+        };
+        let limit = SOFT_LIMIT.load(Ordering::SeqCst);
+        if live > limit && !WARNED.swap(true, Ordering::SeqCst) {
+            // Call the handler without holding the lock, in case it sets
+            // another.
+            let handler = ON_OVER_LIMIT
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .clone();
+            if let Some(handler) = handler {
+                handler(live, limit);
+            }
+        }
+        Registration(())
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let live = LIVE.fetch_sub(1, Ordering::SeqCst) - 1;
+        if live <= SOFT_LIMIT.load(Ordering::SeqCst) {
+            WARNED.store(false, Ordering::SeqCst);
+        }
+        wake_queued();
+    }
+}
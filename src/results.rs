@@ -0,0 +1,222 @@
+// Copyright 2021 Martin Pool
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Readahead combinators for iterators of `Result`s.
+
+use std::sync::mpsc::{channel, Receiver};
+
+use crate::{Readahead, ReadaheadBuilder};
+
+/// Adds readahead methods for handling errors to any iterator of `Result`s.
+pub trait IntoReadaheadResults<V, E>
+where
+    V: Send + 'static,
+{
+    /// Convert the errors with `f` on the worker thread, as for
+    /// [`Result::map_err`], reading ahead from the results.
+    ///
+    /// `Ok` values pass through unchanged.
+    ///
+    /// ```
+    /// use readahead_iterator::IntoReadaheadResults;
+    ///
+    /// let v: Vec<Result<u32, String>> = vec![Ok(1), Err(2), Ok(3)]
+    ///     .into_iter()
+    ///     .readahead_map_err(|code| format!("error {}", code), 4)
+    ///     .collect();
+    /// assert_eq!(v, [Ok(1), Err("error 2".to_owned()), Ok(3)]);
+    /// ```
+    fn readahead_map_err<E2, F>(self, f: F, buffer_size: usize) -> Readahead<Result<V, E2>>
+    where
+        Self: Send + 'static,
+        F: FnMut(E) -> E2 + Send + 'static,
+        E2: Send + 'static;
+
+    /// Read ahead from the `Ok` values, sending the errors to a separate
+    /// channel.
+    ///
+    /// This keeps error handling out of the main processing loop. The errors
+    /// are sent to the returned `Receiver` in order, as the worker reaches
+    /// them; the stream of values continues after each error.
+    ///
+    /// The error channel is unbounded, so the worker never waits for errors to
+    /// be received, and errors that aren't received are held in memory. If
+    /// the `Receiver` is dropped, later errors are discarded.
+    ///
+    /// ```
+    /// use readahead_iterator::IntoReadaheadResults;
+    ///
+    /// let (values, errors) = vec![Ok(1), Err("bad"), Ok(2)]
+    ///     .into_iter()
+    ///     .readahead_tap_errors(4);
+    /// assert_eq!(values.collect::<Vec<u32>>(), [1, 2]);
+    /// assert_eq!(errors.iter().collect::<Vec<_>>(), ["bad"]);
+    /// ```
+    fn readahead_tap_errors(self, buffer_size: usize) -> (Readahead<V>, Receiver<E>)
+    where
+        Self: Send + 'static,
+        E: Send + 'static;
+
+    /// Read ahead, retrying errors for which `should_retry` returns true by
+    /// polling the source again, up to `max_attempts` times in all.
+    ///
+    /// This is for sources where each call to `next()` is a fresh attempt,
+    /// such as a generator reading from a flaky device or connection: a
+    /// retryable error is discarded, and the worker calls the source again
+    /// in its place. It doesn't retry the *same* item, since an iterator
+    /// can't be asked for an item again; for that, retry the operation
+    /// inside the source, for example in a `map` over the inputs.
+    ///
+    /// After `max_attempts` calls for one item, or on an error that isn't to
+    /// be retried, the last result is passed on, and the next item gets a
+    /// fresh count. If the source ends while retrying, the last error is
+    /// passed on before the end of the stream.
+    ///
+    /// The retries happen on the worker, overlapped with the consumer. There's
+    /// no delay between attempts, but `should_retry` runs on the worker too,
+    /// so it can sleep to back off.
+    ///
+    /// Panics if `max_attempts` is zero.
+    ///
+    /// ```
+    /// use readahead_iterator::IntoReadaheadResults;
+    ///
+    /// // A connection that fails twice before each good read.
+    /// let mut calls = 0;
+    /// let reads = std::iter::from_fn(move || {
+    ///     calls += 1;
+    ///     if calls > 9 {
+    ///         None
+    ///     } else if calls % 3 == 0 {
+    ///         Some(Ok(calls))
+    ///     } else {
+    ///         Some(Err("timed out"))
+    ///     }
+    /// });
+    /// let v: Vec<_> = reads.readahead_retry(3, |_| true, 4).collect();
+    /// assert_eq!(v, [Ok(3), Ok(6), Ok(9)]);
+    /// ```
+    fn readahead_retry<F>(
+        self,
+        max_attempts: usize,
+        should_retry: F,
+        buffer_size: usize,
+    ) -> Readahead<Result<V, E>>
+    where
+        Self: Send + 'static,
+        F: Fn(&E) -> bool + Send + 'static,
+        E: Send + 'static;
+
+    /// Read ahead through all the results, and return the `Ok` values and
+    /// the errors separately.
+    ///
+    /// This is for processing everything and then reporting the successes
+    /// and failures, rather than stopping at the first error. Both vectors
+    /// keep the order of the source. The results are produced on the worker
+    /// while the consumer sorts them, and the worker is joined before this
+    /// returns. The vector of values is preallocated from the source's
+    /// [`size_hint`](Iterator::size_hint).
+    ///
+    /// ```
+    /// use readahead_iterator::IntoReadaheadResults;
+    ///
+    /// let (values, errors) = vec![Ok(1), Err("bad"), Ok(2), Err("worse")]
+    ///     .into_iter()
+    ///     .readahead_collect_errors(4);
+    /// assert_eq!(values, [1, 2]);
+    /// assert_eq!(errors, ["bad", "worse"]);
+    /// ```
+    fn readahead_collect_errors(self, buffer_size: usize) -> (Vec<V>, Vec<E>)
+    where
+        Self: Send + 'static,
+        E: Send + 'static;
+}
+
+impl<I, V, E> IntoReadaheadResults<V, E> for I
+where
+    I: Iterator<Item = Result<V, E>>,
+    V: Send + 'static,
+{
+    fn readahead_map_err<E2, F>(self, mut f: F, buffer_size: usize) -> Readahead<Result<V, E2>>
+    where
+        Self: Send + 'static,
+        F: FnMut(E) -> E2 + Send + 'static,
+        E2: Send + 'static,
+    {
+        Readahead::new(self.map(move |r| r.map_err(&mut f)), buffer_size)
+    }
+
+    fn readahead_tap_errors(self, buffer_size: usize) -> (Readahead<V>, Receiver<E>)
+    where
+        Self: Send + 'static,
+        E: Send + 'static,
+    {
+        let (sender, receiver) = channel();
+        let values = self.filter_map(move |r| match r {
+            Ok(value) => Some(value),
+            Err(err) => {
+                let _ = sender.send(err);
+                None
+            }
+        });
+        (Readahead::new(values, buffer_size), receiver)
+    }
+
+    fn readahead_retry<F>(
+        mut self,
+        max_attempts: usize,
+        should_retry: F,
+        buffer_size: usize,
+    ) -> Readahead<Result<V, E>>
+    where
+        Self: Send + 'static,
+        F: Fn(&E) -> bool + Send + 'static,
+        E: Send + 'static,
+    {
+        assert!(max_attempts > 0, "max_attempts must be greater than zero");
+        Readahead::from_generator(
+            move || {
+                let mut last = None;
+                for attempt in 1..=max_attempts {
+                    let result = match self.next() {
+                        Some(result) => result,
+                        None => return last,
+                    };
+                    match &result {
+                        Err(err) if attempt < max_attempts && should_retry(err) => {
+                            last = Some(result)
+                        }
+                        _ => return Some(result),
+                    }
+                }
+                unreachable!("the last attempt always returns")
+            },
+            buffer_size,
+        )
+    }
+
+    fn readahead_collect_errors(self, buffer_size: usize) -> (Vec<V>, Vec<E>)
+    where
+        Self: Send + 'static,
+        E: Send + 'static,
+    {
+        let mut values = Vec::with_capacity(self.size_hint().0);
+        let mut errors = Vec::new();
+        let results = ReadaheadBuilder::new(buffer_size)
+            .join_on_drop(None)
+            .spawn(self)
+            .expect("failed to spawn readahead_iterator thread");
+        for result in results {
+            match result {
+                Ok(value) => values.push(value),
+                Err(err) => errors.push(err),
+            }
+        }
+        (values, errors)
+    }
+}
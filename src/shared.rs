@@ -0,0 +1,297 @@
+// Copyright 2021 Martin Pool
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! State shared between the worker thread and the consumer.
+
+use std::any::Any;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant};
+
+// All the atomics use `SeqCst`: a waiter announces itself and then checks the
+// state, while the worker updates the state and then checks for waiters, and
+// that handshake needs a single total order.
+
+/// Counters and flags updated by the worker and read by the consumer.
+#[derive(Debug, Default)]
+pub(crate) struct Shared {
+    /// Items sent by the worker and not yet received by the consumer.
+    ///
+    /// The worker increments this before sending, and the consumer decrements
+    /// it after receiving, so it never goes negative.
+    buffered: AtomicUsize,
+    /// With an adaptive buffer, the number of items the worker may buffer
+    /// now.
+    limit: AtomicUsize,
+
+    /// The highest value of `buffered` so far.
+    high_water_mark: AtomicUsize,
+
+    /// True once the worker will send no more items, for whatever reason.
+    done: AtomicBool,
+
+    /// True once the inner iterator has returned `None`.
+    source_ended: AtomicBool,
+
+    /// True once the consumer has been dropped.
+    hung_up: AtomicBool,
+
+    /// Total size of the buffered items, if their size is limited.
+    buffered_bytes: AtomicUsize,
+    /// The size of each buffered item, in order, if their size is limited.
+    item_bytes: Mutex<VecDeque<usize>>,
+
+    /// True while the worker is inside the inner iterator's `next()`.
+    producing: AtomicBool,
+
+    /// True while the consumer has paused the worker.
+    paused: AtomicBool,
+
+    /// While the consumer waits for a batch, the number of buffered items it
+    /// wants: the worker doesn't wake it for fewer.
+    wanted: AtomicUsize,
+
+    /// Number of threads waiting on `changed`, so that the worker only needs
+    /// to take the lock when someone's waiting.
+    waiters: AtomicUsize,
+    /// Held while notifying `changed`. It protects no data.
+    lock: Mutex<()>,
+    /// Notified when the worker produces or sends an item, or finishes, when
+    /// the consumer receives an item, frees buffered bytes, pauses or resumes
+    /// the worker, or hangs up, if anyone's waiting.
+    changed: Condvar,
+
+    /// The worker thread, once it's started.
+    worker_thread: Mutex<Option<ThreadId>>,
+
+    /// The payload of a panic on the worker, to be resumed by the consumer.
+    panic: Mutex<Option<Box<dyn Any + Send>>>,
+}
+
+impl Shared {
+    pub(crate) fn buffered(&self) -> usize {
+        self.buffered.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn high_water_mark(&self) -> usize {
+        self.high_water_mark.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn limit(&self) -> usize {
+        self.limit.load(Ordering::SeqCst)
+    }
+
+    /// Set the number of items an adaptive buffer may hold.
+    pub(crate) fn set_limit(&self, limit: usize) {
+        self.limit.store(limit, Ordering::SeqCst);
+    }
+
+    /// Called by the consumer when it finds an adaptive buffer empty: double
+    /// the limit, up to `max`, and wake the worker.
+    pub(crate) fn grow_limit(&self, max: usize) {
+        let _ = self
+            .limit
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |limit| {
+                (limit < max).then(|| limit.saturating_mul(2).min(max))
+            });
+        self.notify();
+    }
+
+    pub(crate) fn is_done(&self) -> bool {
+        self.done.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn is_source_ended(&self) -> bool {
+        self.source_ended.load(Ordering::SeqCst)
+    }
+
+    /// Called by the worker when the inner iterator returns `None`.
+    pub(crate) fn set_source_ended(&self) {
+        self.source_ended.store(true, Ordering::SeqCst);
+    }
+
+    pub(crate) fn is_hung_up(&self) -> bool {
+        self.hung_up.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn buffered_bytes(&self) -> usize {
+        self.buffered_bytes.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn is_producing(&self) -> bool {
+        self.producing.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    /// Called by the consumer to pause or resume the worker.
+    pub(crate) fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::SeqCst);
+        self.notify();
+    }
+
+    /// Called by the worker before producing an item: wait while it's paused,
+    /// unless the consumer hangs up.
+    pub(crate) fn wait_while_paused(&self) {
+        if self.is_paused() {
+            self.wait_until(|s| !s.is_paused() || s.is_hung_up(), None);
+        }
+    }
+
+    /// Wait until `condition` is true, or the timeout expires.
+    ///
+    /// The condition is rechecked each time the worker sends an item or
+    /// finishes. Returns the final value of the condition.
+    pub(crate) fn wait_until<C>(&self, condition: C, timeout: Option<Duration>) -> bool
+    where
+        C: Fn(&Shared) -> bool,
+    {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        self.waiters.fetch_add(1, Ordering::SeqCst);
+        let mut guard = self.lock.lock().unwrap();
+        let result = loop {
+            if condition(self) {
+                break true;
+            }
+            match deadline {
+                None => guard = self.changed.wait(guard).unwrap(),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        break false;
+                    }
+                    guard = self.changed.wait_timeout(guard, deadline - now).unwrap().0;
+                }
+            }
+        };
+        self.waiters.fetch_sub(1, Ordering::SeqCst);
+        result
+    }
+
+    /// Wait until the worker is done, or until the timeout expires.
+    ///
+    /// Returns true if the worker is done.
+    pub(crate) fn wait_done(&self, timeout: Option<Duration>) -> bool {
+        self.wait_until(Shared::is_done, timeout)
+    }
+
+    /// Wake anyone waiting for the state to change.
+    fn notify(&self) {
+        if self.waiters.load(Ordering::SeqCst) > 0 {
+            let _guard = self.lock.lock();
+            self.changed.notify_all();
+        }
+    }
+
+    /// Wake the consumer, unless it's waiting for a batch that isn't ready.
+    ///
+    /// Only the consumer waits for the worker, so this is used for changes
+    /// the worker makes.
+    fn notify_consumer(&self) {
+        if self.buffered() >= self.wanted.load(Ordering::SeqCst) {
+            self.notify();
+        }
+    }
+
+    /// Wait until at least `n` items are buffered, or the worker is done.
+    pub(crate) fn wait_for_batch(&self, n: usize) {
+        self.wanted.store(n, Ordering::SeqCst);
+        self.wait_until(|s| s.buffered() >= n || s.is_done(), None);
+        self.wanted.store(0, Ordering::SeqCst);
+    }
+
+    /// Called by the worker when it starts.
+    pub(crate) fn set_worker_thread(&self) {
+        *self.worker_thread.lock().unwrap() = Some(thread::current().id());
+    }
+
+    /// True if this is called on the worker thread.
+    pub(crate) fn is_worker_thread(&self) -> bool {
+        *self.worker_thread.lock().unwrap() == Some(thread::current().id())
+    }
+
+    /// Called by the worker to pass a panic to the consumer.
+    pub(crate) fn set_panic(&self, payload: Box<dyn Any + Send>) {
+        *self.panic.lock().unwrap() = Some(payload);
+    }
+
+    /// Called by the consumer to take a panic passed by the worker.
+    pub(crate) fn take_panic(&self) -> Option<Box<dyn Any + Send>> {
+        self.panic.lock().unwrap().take()
+    }
+
+    /// Called by the worker just before it asks the inner iterator for an item.
+    pub(crate) fn will_produce(&self) {
+        self.producing.store(true, Ordering::SeqCst);
+    }
+
+    /// Called by the worker when the inner iterator returns.
+    pub(crate) fn did_produce(&self) {
+        self.producing.store(false, Ordering::SeqCst);
+        self.notify_consumer();
+    }
+
+    /// Called by the worker just before it sends an item.
+    pub(crate) fn will_send(&self) {
+        let buffered = self.buffered.fetch_add(1, Ordering::SeqCst) + 1;
+        self.high_water_mark.fetch_max(buffered, Ordering::SeqCst);
+    }
+
+    /// Called by the worker after it successfully sends an item.
+    pub(crate) fn did_send(&self) {
+        self.notify_consumer();
+    }
+
+    /// Called by the worker if an item it announced with `will_send` was not
+    /// sent after all.
+    pub(crate) fn not_sent(&self) {
+        self.buffered.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    /// Called by the consumer after it receives an item, or, for LIFO or
+    /// priority delivery, when it delivers one it was holding.
+    pub(crate) fn did_receive(&self) {
+        self.buffered.fetch_sub(1, Ordering::SeqCst);
+        self.notify();
+    }
+
+    /// Called by the worker, before sending an item, to add its size to the
+    /// buffered bytes.
+    pub(crate) fn add_bytes(&self, size: usize) {
+        self.item_bytes.lock().unwrap().push_back(size);
+        self.buffered_bytes.fetch_add(size, Ordering::SeqCst);
+    }
+
+    /// Called by the consumer after it receives an item whose size was added
+    /// by `add_bytes`.
+    pub(crate) fn remove_bytes(&self) {
+        let size = self.item_bytes.lock().unwrap().pop_front().unwrap_or(0);
+        self.buffered_bytes.fetch_sub(size, Ordering::SeqCst);
+        self.notify();
+    }
+
+    /// Called when the consumer is dropped, to wake a waiting worker.
+    pub(crate) fn hang_up(&self) {
+        self.hung_up.store(true, Ordering::SeqCst);
+        self.notify();
+    }
+}
+
+/// Marks the worker as done when dropped, including when it unwinds.
+pub(crate) struct DoneGuard(pub(crate) Arc<Shared>);
+
+impl Drop for DoneGuard {
+    fn drop(&mut self) {
+        self.0.done.store(true, Ordering::SeqCst);
+        self.0.notify();
+    }
+}
@@ -0,0 +1,72 @@
+// Copyright 2021 Martin Pool
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Stopping production from another thread.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Stops the worker of a [`Readahead`](crate::Readahead) from producing more
+/// items.
+///
+/// Created by
+/// [`IntoReadahead::readahead_with_shutdown`](crate::IntoReadahead::readahead_with_shutdown).
+/// It can be cloned, and moved to any thread.
+#[derive(Debug, Clone)]
+pub struct ShutdownHandle {
+    stopped: Arc<AtomicBool>,
+}
+
+impl ShutdownHandle {
+    /// Ask the worker to stop.
+    ///
+    /// The worker finishes the item it's producing, if any, and then ends the
+    /// stream. Items already produced are still delivered, so the consumer's
+    /// `next()` returns `None` once it has drained them.
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+
+    /// True once [`stop`](ShutdownHandle::stop) has been called.
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::SeqCst)
+    }
+}
+
+/// Passes items through from `inner` until shutdown is requested.
+pub(crate) struct UntilShutdown<I> {
+    inner: I,
+    handle: ShutdownHandle,
+}
+
+impl<I> UntilShutdown<I> {
+    pub(crate) fn new(inner: I) -> (Self, ShutdownHandle) {
+        let handle = ShutdownHandle {
+            stopped: Arc::new(AtomicBool::new(false)),
+        };
+        (
+            UntilShutdown {
+                inner,
+                handle: handle.clone(),
+            },
+            handle,
+        )
+    }
+}
+
+impl<I: Iterator> Iterator for UntilShutdown<I> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        if self.handle.is_stopped() {
+            None
+        } else {
+            self.inner.next()
+        }
+    }
+}
@@ -0,0 +1,76 @@
+// Copyright 2021 Martin Pool
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Measurements of how full the buffer gets, and how fast it's drained.
+
+use std::time::Duration;
+
+/// A snapshot of the state of a readahead's buffer, for tuning its size.
+///
+/// Returned by [`Readahead::stats`](crate::Readahead::stats).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReadaheadStats {
+    pub(crate) buffered: usize,
+    pub(crate) high_water_mark: usize,
+}
+
+impl ReadaheadStats {
+    /// The number of items buffered when the snapshot was taken.
+    pub fn buffered(&self) -> usize {
+        self.buffered
+    }
+
+    /// The most items that have been buffered at once, so far.
+    ///
+    /// If this stays well below the buffer size over a whole run, the buffer
+    /// could be smaller. If it reaches the buffer size, the worker has got as
+    /// far ahead as it can, which usually means the consumer is the
+    /// bottleneck, and a bigger buffer won't help.
+    pub fn high_water_mark(&self) -> usize {
+        self.high_water_mark
+    }
+}
+
+/// The throughput of a whole stream, for benchmarks.
+///
+/// Returned by [`Readahead::drain_timed`](crate::Readahead::drain_timed).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DrainReport {
+    pub(crate) items: usize,
+    pub(crate) elapsed: Duration,
+    pub(crate) high_water_mark: usize,
+}
+
+impl DrainReport {
+    /// The number of items consumed.
+    pub fn items(&self) -> usize {
+        self.items
+    }
+
+    /// The time taken to consume them.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// The number of items consumed per second, or zero if no time was
+    /// measured.
+    pub fn items_per_sec(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 {
+            self.items as f64 / secs
+        } else {
+            0.0
+        }
+    }
+
+    /// The most items buffered at once, as for
+    /// [`ReadaheadStats::high_water_mark`], or zero if it's not known.
+    pub fn high_water_mark(&self) -> usize {
+        self.high_water_mark
+    }
+}
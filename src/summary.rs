@@ -0,0 +1,102 @@
+// Copyright 2021 Martin Pool
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A readahead iterator that hands back a summary computed by the worker.
+
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+
+use crate::Readahead;
+
+/// A [`Readahead`] whose worker also computes a summary of all the items,
+/// available once the stream ends.
+///
+/// Created by
+/// [`IntoReadahead::readahead_with_summary`](crate::IntoReadahead::readahead_with_summary).
+pub struct ReadaheadWithSummary<T: Send + 'static, S> {
+    inner: Readahead<T>,
+    summary: Receiver<S>,
+}
+
+impl<T, S> ReadaheadWithSummary<T, S>
+where
+    T: Send + 'static,
+{
+    pub(crate) fn new(inner: Readahead<T>, summary: Receiver<S>) -> Self {
+        ReadaheadWithSummary { inner, summary }
+    }
+
+    /// Take the summary, once `next()` has returned `None`.
+    ///
+    /// Returns `None` before the end of the stream, if the summary has
+    /// already been taken, or if the worker never reached the end of the
+    /// inner iterator because it panicked.
+    pub fn take_summary(&mut self) -> Option<S> {
+        if !self.inner.done {
+            return None;
+        }
+        self.summary.try_recv().ok()
+    }
+}
+
+impl<T, S> Iterator for ReadaheadWithSummary<T, S>
+where
+    T: Send + 'static,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.inner.next()
+    }
+}
+
+/// Folds each item into a summary as it passes through, and sends the
+/// summary at the end of the stream.
+pub(crate) struct Summarize<I, S, F> {
+    inner: I,
+    /// `None` once the summary has been sent.
+    state: Option<S>,
+    f: F,
+    sender: SyncSender<S>,
+}
+
+impl<I, S, F> Summarize<I, S, F> {
+    pub(crate) fn new(inner: I, init: S, f: F) -> (Self, Receiver<S>) {
+        let (sender, receiver) = sync_channel(1);
+        let summarize = Summarize {
+            inner,
+            state: Some(init),
+            f,
+            sender,
+        };
+        (summarize, receiver)
+    }
+}
+
+impl<I, S, F> Iterator for Summarize<I, S, F>
+where
+    I: Iterator,
+    F: FnMut(&mut S, &I::Item),
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        let state = self.state.as_mut()?;
+        match self.inner.next() {
+            Some(item) => {
+                (self.f)(state, &item);
+                Some(item)
+            }
+            None => {
+                // This is sent before the end of the stream, so it's waiting
+                // by the time the consumer sees the end.
+                let _ = self.sender.send(self.state.take().unwrap());
+                None
+            }
+        }
+    }
+}
@@ -0,0 +1,133 @@
+// Copyright 2021 Martin Pool
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Borrow items in a sliding window rather than taking them by value.
+
+use std::collections::VecDeque;
+
+use crate::Readahead;
+
+/// A cursor over a [`Readahead`] that keeps recently consumed items alive, so
+/// that they can be borrowed rather than moved or cloned, and that can look
+/// ahead at the items after the current one.
+///
+/// Created by [`Readahead::windowed`], which only looks behind, or by
+/// [`IntoReadahead::readahead_window`](crate::IntoReadahead::readahead_window).
+///
+/// This is not an `Iterator`, because iterators can't lend out references to
+/// their own storage. Instead, call [`advance`](ReadaheadWindow::advance) to
+/// move to the next item, and then borrow it, or the items before it.
+///
+/// ```
+/// use readahead_iterator::Readahead;
+///
+/// let mut window = Readahead::new(1..=4, 10).windowed(2);
+/// let mut sums = Vec::new();
+/// while let Some(current) = window.advance() {
+///     let current = *current;
+///     let previous = window.behind(1).copied().unwrap_or(0);
+///     sums.push(previous + current);
+/// }
+/// assert_eq!(sums, [1, 3, 5, 7]);
+/// ```
+pub struct ReadaheadWindow<T: Send + 'static> {
+    inner: Readahead<T>,
+    current: Option<T>,
+    /// Previously current items, most recent first.
+    behind: VecDeque<T>,
+    lookbehind: usize,
+    /// Items received after the current one, in order.
+    ahead: VecDeque<T>,
+    lookahead: usize,
+}
+
+impl<T> ReadaheadWindow<T>
+where
+    T: Send + 'static,
+{
+    pub(crate) fn new(inner: Readahead<T>, lookbehind: usize, lookahead: usize) -> Self {
+        ReadaheadWindow {
+            inner,
+            current: None,
+            behind: VecDeque::with_capacity(lookbehind),
+            lookbehind,
+            ahead: VecDeque::with_capacity(lookahead),
+            lookahead,
+        }
+    }
+
+    /// Move to the next item, and borrow it.
+    ///
+    /// The previously current item is retained, and the oldest retained item
+    /// is dropped if there are more than `lookbehind` of them.
+    ///
+    /// Returns `None` at the end of the stream. The retained items stay
+    /// available, so `behind(1)` is then the last item of the stream.
+    pub fn advance(&mut self) -> Option<&T> {
+        if let Some(previous) = self.current.take() {
+            if self.lookbehind > 0 {
+                if self.behind.len() == self.lookbehind {
+                    self.behind.pop_back();
+                }
+                self.behind.push_front(previous);
+            }
+        }
+        self.current = match self.ahead.pop_front() {
+            Some(next) => Some(next),
+            None => self.inner.next(),
+        };
+        self.current.as_ref()
+    }
+
+    /// Borrow the current item: the one most recently returned by `advance`.
+    ///
+    /// Returns `None` before the first call to `advance`, and at the end of the
+    /// stream.
+    pub fn current(&self) -> Option<&T> {
+        self.current.as_ref()
+    }
+
+    /// Borrow the item `i` steps before the current one.
+    ///
+    /// `behind(0)` is the current item. Returns `None` if `i` is more than
+    /// the `lookbehind` of this window, or reaches back before the start of the
+    /// stream.
+    pub fn behind(&self, i: usize) -> Option<&T> {
+        match i {
+            0 => self.current(),
+            i => self.behind.get(i - 1),
+        }
+    }
+
+    /// Borrow the item `i` steps after the current one, waiting for it to be
+    /// produced if necessary.
+    ///
+    /// `ahead(0)` is the current item. Returns `None` if `i` is more than the
+    /// `lookahead` of this window, or reaches past the end of the stream.
+    /// Before the first call to `advance`, `ahead(1)` is the first item.
+    pub fn ahead(&mut self, i: usize) -> Option<&T> {
+        if i == 0 {
+            return self.current();
+        }
+        if i > self.lookahead {
+            return None;
+        }
+        while self.ahead.len() < i {
+            self.ahead.push_back(self.inner.next()?);
+        }
+        self.ahead.get(i - 1)
+    }
+
+    /// Return the underlying `Readahead`, dropping any retained items.
+    ///
+    /// The current item, and any items looked ahead at, are also dropped: the
+    /// `Readahead` continues from the item after them.
+    pub fn into_inner(self) -> Readahead<T> {
+        self.inner
+    }
+}
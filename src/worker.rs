@@ -0,0 +1,299 @@
+// Copyright 2021 Martin Pool
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The worker thread that runs the inner iterator.
+
+use std::hint;
+use std::iter;
+use std::panic::{self, AssertUnwindSafe};
+use std::process;
+use std::sync::mpsc::{SendError, Sender, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::builder::{Coalesce, CompletionHandler, PanicHandler, PanicPolicy, SizeFn, SlowHandler};
+use crate::clock::Clock;
+#[cfg(feature = "registry")]
+use crate::registry::Registration;
+use crate::shared::{DoneGuard, Shared};
+
+/// With `throttle_on_lag`, the number of sends in a row that find the buffer
+/// full before the worker starts sleeping.
+const LAG_SENDS: usize = 4;
+
+/// With `throttle_on_lag`, how long the worker sleeps after each send while
+/// the consumer is lagging.
+const LAG_SLEEP: Duration = Duration::from_millis(1);
+
+/// The sending side of either a bounded or an unbounded channel.
+pub(crate) enum WorkerSender<T> {
+    Bounded(SyncSender<Option<T>>),
+    Unbounded(Sender<Option<T>>),
+}
+
+impl<T> WorkerSender<T> {
+    fn send(&self, item: Option<T>) -> Result<(), SendError<Option<T>>> {
+        match self {
+            WorkerSender::Bounded(sender) => sender.send(item),
+            WorkerSender::Unbounded(sender) => sender.send(item),
+        }
+    }
+
+    fn try_send(&self, item: Option<T>) -> Result<(), TrySendError<Option<T>>> {
+        match self {
+            WorkerSender::Bounded(sender) => sender.try_send(item),
+            WorkerSender::Unbounded(sender) => sender
+                .send(item)
+                .map_err(|SendError(item)| TrySendError::Disconnected(item)),
+        }
+    }
+}
+
+/// Everything the worker thread needs, apart from the inner iterator.
+pub(crate) struct Worker<T> {
+    pub(crate) sender: WorkerSender<T>,
+    pub(crate) shared: Arc<Shared>,
+    pub(crate) on_panic: Option<PanicHandler>,
+    pub(crate) on_complete: Option<CompletionHandler>,
+    pub(crate) panic_policy: PanicPolicy,
+    pub(crate) coalesce: Option<Coalesce<T>>,
+    /// Receives the source position of each item sent, if ordering is being
+    /// verified.
+    pub(crate) sequence: Option<Sender<usize>>,
+    /// The maximum total size of buffered items, and how to measure them.
+    pub(crate) byte_limit: Option<(usize, SizeFn<T>)>,
+    /// Number of times to retry a send to a full buffer before blocking.
+    pub(crate) spin_before_block: usize,
+    /// Sleep after sending while the buffer keeps being full.
+    pub(crate) throttle_on_lag: bool,
+    /// For LIFO or priority delivery, the most items that can be buffered or
+    /// held by the consumer waiting to be delivered.
+    pub(crate) hold_limit: Option<usize>,
+    /// True if the buffer's size is limited by the shared, adaptive limit,
+    /// rather than only by the channel.
+    pub(crate) adaptive: bool,
+    /// When to stop taking items from the inner iterator.
+    pub(crate) deadline: Option<Instant>,
+    /// Called with the position and production time of items that take
+    /// longer than the threshold to produce.
+    pub(crate) warn_slow: Option<(Duration, SlowHandler)>,
+    pub(crate) clock: Arc<dyn Clock>,
+}
+
+impl<T> Worker<T> {
+    /// Body of the worker thread: build the inner iterator with `make_inner`,
+    /// and then send items from it until it's exhausted or the receiver hangs
+    /// up.
+    pub(crate) fn run<F, I>(mut self, make_inner: F)
+    where
+        F: FnOnce() -> I,
+        I: Iterator<Item = T>,
+    {
+        let _done_guard = DoneGuard(self.shared.clone());
+        if cfg!(debug_assertions) {
+            self.shared.set_worker_thread();
+        }
+        #[cfg(feature = "registry")]
+        let _registration = Registration::new(&self.shared);
+        let on_complete = self.on_complete.take();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let mut inner = make_inner();
+            let finished = match &self.coalesce {
+                None => self.send_all(&mut inner),
+                Some(coalesce) => self.send_coalescing(&mut inner, coalesce),
+            };
+            if finished {
+                // Stopping at the deadline isn't completion.
+                if self.shared.is_source_ended() {
+                    if let Some(on_complete) = on_complete {
+                        on_complete();
+                    }
+                }
+                let _ = self.sender.send(None);
+            }
+            // Drop the inner iterator only after sending the end of the
+            // stream, so that any work it does when dropped overlaps with the
+            // consumer.
+            drop(inner);
+        }));
+        match result {
+            Ok(()) => (),
+            Err(payload) => {
+                let payload = match self.on_panic {
+                    Some(handler) => {
+                        handler(payload);
+                        Box::new("readahead_iterator worker panicked")
+                    }
+                    None => payload,
+                };
+                match self.panic_policy {
+                    PanicPolicy::Swallow => {
+                        let _ = self.sender.send(None);
+                    }
+                    // Hang up without sending `None`, so the consumer knows to
+                    // look for the panic once it's received everything else.
+                    PanicPolicy::Propagate => self.shared.set_panic(payload),
+                    PanicPolicy::Abort => process::abort(),
+                }
+            }
+        }
+    }
+
+    /// Send every item, blocking while the buffer is full.
+    ///
+    /// Returns false if the consumer hung up.
+    fn send_all<I>(&self, inner: I) -> bool
+    where
+        I: Iterator<Item = T>,
+    {
+        let mut full_sends = 0;
+        for (seq, item) in self.produce(inner).enumerate() {
+            if let Some((limit, size_fn)) = &self.byte_limit {
+                let size = size_fn(&item);
+                // An item bigger than the limit is sent once the buffer is
+                // empty, rather than never.
+                self.shared.wait_until(
+                    |s| {
+                        s.buffered_bytes() == 0
+                            || s.buffered_bytes() + size <= *limit
+                            || s.is_hung_up()
+                    },
+                    None,
+                );
+                self.shared.add_bytes(size);
+            }
+            if self.adaptive {
+                self.shared
+                    .wait_until(|s| s.buffered() < s.limit() || s.is_hung_up(), None);
+            }
+            if let Some(limit) = self.hold_limit {
+                self.shared
+                    .wait_until(|s| s.buffered() < limit || s.is_hung_up(), None);
+            }
+            self.shared.will_send();
+            let was_full = match self.send_spinning(item) {
+                Ok(was_full) => was_full,
+                // The consumer was dropped; nobody wants any more items.
+                Err(_) => return false,
+            };
+            self.did_send(seq);
+            if self.throttle_on_lag {
+                full_sends = if was_full { full_sends + 1 } else { 0 };
+                if full_sends >= LAG_SENDS {
+                    thread::sleep(LAG_SLEEP);
+                }
+            }
+        }
+        true
+    }
+
+    /// Send an item, first retrying a full buffer without blocking, if
+    /// configured.
+    ///
+    /// Returns true if the buffer was full when the item was first offered.
+    fn send_spinning(&self, item: T) -> Result<bool, SendError<Option<T>>> {
+        let mut item = Some(item);
+        for spin in 0..=self.spin_before_block {
+            match self.sender.try_send(item) {
+                Ok(()) => return Ok(spin > 0),
+                Err(TrySendError::Full(returned)) => {
+                    item = returned;
+                    hint::spin_loop();
+                }
+                Err(TrySendError::Disconnected(returned)) => return Err(SendError(returned)),
+            }
+        }
+        self.sender.send(item).map(|()| true)
+    }
+
+    /// Send items without blocking, folding items together while the buffer is
+    /// full.
+    ///
+    /// Returns false if the consumer hung up.
+    fn send_coalescing<I>(&self, inner: I, coalesce: &Coalesce<T>) -> bool
+    where
+        I: Iterator<Item = T>,
+    {
+        let mut pending: Option<T> = None;
+        let mut seq = 0;
+        for (i, item) in self.produce(inner).enumerate() {
+            seq = i;
+            let item = match pending.take() {
+                Some(older) => coalesce(older, item),
+                None => item,
+            };
+            self.shared.will_send();
+            match self.sender.try_send(Some(item)) {
+                Ok(()) => self.did_send(seq),
+                Err(TrySendError::Full(item)) => {
+                    self.shared.not_sent();
+                    pending = item;
+                }
+                Err(TrySendError::Disconnected(_)) => return false,
+            }
+        }
+        if let Some(item) = pending {
+            self.shared.will_send();
+            if self.sender.send(Some(item)).is_err() {
+                return false;
+            }
+            self.did_send(seq);
+        }
+        true
+    }
+
+    /// Pull items from `inner`, noting when the worker is inside its `next()`,
+    /// and waiting first while the worker is paused.
+    ///
+    /// Ends early once the deadline, if any, has passed, and warns about slow
+    /// items if configured.
+    fn produce<'a, I>(&'a self, mut inner: I) -> impl Iterator<Item = T> + 'a
+    where
+        I: Iterator<Item = T> + 'a,
+    {
+        let mut index = 0;
+        iter::from_fn(move || {
+            self.shared.wait_while_paused();
+            let start = match (self.deadline, &self.warn_slow) {
+                (None, None) => None,
+                _ => Some(self.clock.now()),
+            };
+            if let (Some(deadline), Some(start)) = (self.deadline, start) {
+                if start >= deadline {
+                    return None;
+                }
+            }
+            self.shared.will_produce();
+            let item = inner.next();
+            if let (Some((threshold, on_slow)), Some(start), Some(_)) =
+                (&self.warn_slow, start, &item)
+            {
+                let elapsed = self.clock.now().saturating_duration_since(start);
+                if elapsed > *threshold {
+                    on_slow(index, elapsed);
+                }
+            }
+            index += 1;
+            if item.is_none() {
+                self.shared.set_source_ended();
+            }
+            self.shared.did_produce();
+            item
+        })
+    }
+
+    /// Record that the item at position `seq` in the source, or a coalesced
+    /// item ending there, was sent.
+    fn did_send(&self, seq: usize) {
+        if let Some(sequence) = &self.sequence {
+            let _ = sequence.send(seq);
+        }
+        self.shared.did_send();
+    }
+}
@@ -0,0 +1,51 @@
+// Copyright 2021 Martin Pool
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Zipping two readaheads together.
+
+use crate::Readahead;
+
+/// Yields pairs of items from two readaheads, until either ends.
+///
+/// Created by [`IntoReadahead::readahead_zip`](crate::IntoReadahead::readahead_zip).
+#[derive(Debug)]
+pub struct ReadaheadZip<A: Send + 'static, B: Send + 'static> {
+    /// Both sides, until either ends.
+    sources: Option<(Readahead<A>, Readahead<B>)>,
+}
+
+impl<A, B> ReadaheadZip<A, B>
+where
+    A: Send + 'static,
+    B: Send + 'static,
+{
+    pub(crate) fn new(a: Readahead<A>, b: Readahead<B>) -> Self {
+        ReadaheadZip {
+            sources: Some((a, b)),
+        }
+    }
+}
+
+impl<A, B> Iterator for ReadaheadZip<A, B>
+where
+    A: Send + 'static,
+    B: Send + 'static,
+{
+    type Item = (A, B);
+
+    fn next(&mut self) -> Option<(A, B)> {
+        let (a, b) = self.sources.as_mut()?;
+        let pair = a.next().and_then(|a| b.next().map(|b| (a, b)));
+        if pair.is_none() {
+            // Drop both sides, so that the worker of the longer one stops,
+            // rather than waiting on a full buffer for as long as this lives.
+            self.sources = None;
+        }
+        pair
+    }
+}
@@ -0,0 +1,655 @@
+// Copyright 2021 Martin Pool
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, sleep};
+use std::time::{Duration, Instant};
+
+use readahead_iterator::{
+    Clock, DeliveryOrder, IntoReadahead, ManualClock, PanicPolicy, Readahead, ReadaheadBuilder,
+};
+
+fn current_thread_name() -> Option<String> {
+    thread::current().name().map(str::to_owned)
+}
+
+#[test]
+fn default_thread_name() {
+    let names: Vec<_> = (0..3).map(|_| current_thread_name()).readahead(2).collect();
+    assert_eq!(names, vec![Some("readahead_iterator".to_owned()); 3]);
+}
+
+#[test]
+fn thread_name_with_id() {
+    let names: Vec<_> =
+        Readahead::new_with_id((0..2).map(|_| current_thread_name()), 2, 42).collect();
+    assert_eq!(names, vec![Some("readahead_iterator-42".to_owned()); 2]);
+}
+
+/// A thread builder without a name leaves the worker unnamed.
+#[test]
+fn thread_builder_without_name() {
+    let names: Vec<_> = Readahead::new_with_thread_builder(
+        (0..2).map(|_| current_thread_name()),
+        2,
+        thread::Builder::new(),
+    )
+    .unwrap()
+    .collect();
+    assert_eq!(names, [None, None]);
+}
+
+/// Many workers with small stacks all run simple iterators correctly.
+#[test]
+fn many_small_stack_workers() {
+    let readaheads: Vec<Readahead<u64>> = (0..200)
+        .map(|i| {
+            ReadaheadBuilder::new(4)
+                .small_stack()
+                .spawn((0..1000).map(move |j| i * j))
+                .unwrap()
+        })
+        .collect();
+    for (i, rah) in readaheads.into_iter().enumerate() {
+        assert_eq!(rah.sum::<u64>(), i as u64 * 499_500);
+    }
+}
+
+#[test]
+fn custom_thread_name_and_stack_size() {
+    let names: Vec<_> = ReadaheadBuilder::new(2)
+        .name("custom")
+        .stack_size(256 << 10)
+        .spawn((0..3).map(|_| current_thread_name()))
+        .unwrap()
+        .collect();
+    assert_eq!(names, vec![Some("custom".to_owned()); 3]);
+}
+
+/// Sets a flag when dropped, to observe when the inner iterator is dropped.
+struct DropFlag(Arc<AtomicBool>);
+
+impl Drop for DropFlag {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn join_on_drop_waits_for_worker() {
+    let dropped = Arc::new(AtomicBool::new(false));
+    let flag = DropFlag(dropped.clone());
+    let mut rah = ReadaheadBuilder::new(1)
+        .join_on_drop(None)
+        .spawn((0..).inspect(move |_| {
+            let _ = &flag;
+        }))
+        .unwrap();
+    assert_eq!(rah.next(), Some(0));
+    drop(rah);
+    assert!(dropped.load(Ordering::SeqCst));
+}
+
+#[test]
+fn join_on_drop_times_out_on_blocked_worker() {
+    let mut rah = ReadaheadBuilder::new(1)
+        .join_on_drop(Some(Duration::from_millis(50)))
+        .spawn((0..).inspect(|i| {
+            if *i > 0 {
+                sleep(Duration::from_secs(2));
+            }
+        }))
+        .unwrap();
+    assert_eq!(rah.next(), Some(0));
+    let start = Instant::now();
+    drop(rah);
+    assert!(start.elapsed() < Duration::from_secs(1));
+}
+
+/// An unbounded worker runs to the end without waiting for the consumer.
+#[test]
+fn unbounded_runs_ahead() {
+    let produced = Arc::new(AtomicUsize::new(0));
+    let produced_clone = produced.clone();
+    let rah = ReadaheadBuilder::unbounded()
+        .spawn((0..1000).inspect(move |_| {
+            produced_clone.fetch_add(1, Ordering::SeqCst);
+        }))
+        .unwrap();
+    for _ in 0..1000 {
+        if produced.load(Ordering::SeqCst) == 1000 {
+            break;
+        }
+        sleep(Duration::from_millis(1));
+    }
+    assert_eq!(produced.load(Ordering::SeqCst), 1000);
+    assert_eq!(rah.count(), 1000);
+}
+
+/// Records its number in a shared log when dropped.
+struct LogOnDrop(usize, Arc<Mutex<Vec<usize>>>);
+
+impl Drop for LogOnDrop {
+    fn drop(&mut self) {
+        self.1.lock().unwrap().push(self.0);
+    }
+}
+
+#[test]
+fn drop_in_order_drops_buffered_items_in_source_order() {
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let log_clone = log.clone();
+    let mut rah = ReadaheadBuilder::new(10)
+        .drop_in_order(true)
+        .join_on_drop(None)
+        .spawn((0..5).map(move |i| LogOnDrop(i, log_clone.clone())))
+        .unwrap();
+    drop(rah.next());
+    // Let the worker fill the buffer and finish.
+    sleep(Duration::from_millis(50));
+    drop(rah);
+    assert_eq!(*log.lock().unwrap(), [0, 1, 2, 3, 4]);
+}
+
+/// By default, a panic on the worker is resumed on the consumer, after the
+/// items produced before it.
+#[test]
+fn worker_panic_propagates_to_consumer() {
+    let mut rah = (0..5)
+        .map(|i| if i < 2 { i } else { panic!("oops at {}", i) })
+        .readahead(4);
+    assert_eq!(rah.next(), Some(0));
+    assert_eq!(rah.next(), Some(1));
+    let err = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| rah.next())).unwrap_err();
+    assert_eq!(err.downcast_ref::<String>().unwrap(), "oops at 2");
+}
+
+/// An explicit `Propagate` overrides the `Swallow` implied by `on_panic`. The
+/// handler gets the original payload, and the consumer a generic one.
+#[test]
+fn on_panic_with_propagate_resumes_generic_payload() {
+    let seen = Arc::new(Mutex::new(None));
+    let seen_clone = seen.clone();
+    let mut rah = ReadaheadBuilder::new(4)
+        .on_panic(move |payload| {
+            *seen_clone.lock().unwrap() = payload.downcast_ref::<&str>().map(|s| s.to_string());
+        })
+        .panic_policy(PanicPolicy::Propagate)
+        .spawn((0..5).map(|i| if i < 1 { i } else { panic!("oops") }))
+        .unwrap();
+    assert_eq!(rah.next(), Some(0));
+    let err = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| rah.next())).unwrap_err();
+    assert_eq!(
+        *err.downcast_ref::<&str>().unwrap(),
+        "readahead_iterator worker panicked"
+    );
+    assert_eq!(seen.lock().unwrap().as_deref(), Some("oops"));
+}
+
+/// Set in the child process run by `abort_policy_kills_process`.
+const ABORT_CHILD_VAR: &str = "READAHEAD_ITERATOR_ABORT_CHILD";
+
+/// `PanicPolicy::Abort` kills the whole process, so this re-runs the test
+/// binary, for just this test, and checks how the child exits.
+#[test]
+fn abort_policy_kills_process() {
+    if std::env::var_os(ABORT_CHILD_VAR).is_some() {
+        let rah = ReadaheadBuilder::new(4)
+            .panic_policy(PanicPolicy::Abort)
+            .spawn((0..5).map(|i| if i < 1 { i } else { panic!("oops") }))
+            .unwrap();
+        let _ = rah.count();
+        // Not reached: the worker aborts the process.
+        std::process::exit(0);
+    }
+    let status = std::process::Command::new(std::env::current_exe().unwrap())
+        .args(["--exact", "abort_policy_kills_process", "--nocapture"])
+        .env(ABORT_CHILD_VAR, "1")
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .unwrap();
+    assert!(!status.success(), "{:?}", status);
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        assert_eq!(status.signal(), Some(6), "expected SIGABRT: {:?}", status);
+    }
+}
+
+/// A lazy worker doesn't start until the first call to `next()`, and is never
+/// started if the `Readahead` is dropped first.
+#[test]
+fn lazy_spawns_on_first_next() {
+    let started = Arc::new(AtomicBool::new(false));
+    let started_clone = started.clone();
+    let mut rah = ReadaheadBuilder::new(4)
+        .lazy(true)
+        .join_on_drop(None)
+        .spawn((0..10).inspect(move |_| started_clone.store(true, Ordering::SeqCst)))
+        .unwrap();
+    rah.warm_up(4);
+    sleep(Duration::from_millis(50));
+    assert!(!started.load(Ordering::SeqCst));
+    assert_eq!(rah.next(), Some(0));
+    assert!(started.load(Ordering::SeqCst));
+    drop(rah);
+
+    let dropped = Arc::new(AtomicBool::new(false));
+    let flag = DropFlag(dropped.clone());
+    let rah = ReadaheadBuilder::new(4)
+        .lazy(true)
+        .join_on_drop(None)
+        .spawn((0..).inspect(move |_| {
+            let _ = &flag;
+        }))
+        .unwrap();
+    drop(rah);
+    assert!(dropped.load(Ordering::SeqCst));
+}
+
+/// Ordering is preserved through plain and coalescing workers.
+#[test]
+fn verify_ordering_passes() {
+    let v: Vec<u32> = ReadaheadBuilder::new(2)
+        .verify_ordering(true)
+        .spawn(0..10_000)
+        .unwrap()
+        .collect();
+    assert_eq!(v.len(), 10_000);
+    let sum: u64 = ReadaheadBuilder::new(1)
+        .verify_ordering(true)
+        .coalesce(|a, b| a + b)
+        .spawn(1..=10_000u64)
+        .unwrap()
+        .sum();
+    assert_eq!(sum, 50_005_000);
+}
+
+/// With `linger`, drop waits for the item in progress, but doesn't let the
+/// worker start another.
+#[test]
+fn linger_finishes_current_item() {
+    let finished = Arc::new(AtomicUsize::new(0));
+    let finished_clone = finished.clone();
+    let mut rah = ReadaheadBuilder::new(1)
+        .linger(Duration::from_secs(5))
+        .spawn((0..10).inspect(move |_| {
+            sleep(Duration::from_millis(100));
+            finished_clone.fetch_add(1, Ordering::SeqCst);
+        }))
+        .unwrap();
+    assert_eq!(rah.next(), Some(0));
+    // The worker is now partway through producing the second item.
+    sleep(Duration::from_millis(20));
+    drop(rah);
+    assert_eq!(finished.load(Ordering::SeqCst), 2);
+    sleep(Duration::from_millis(200));
+    assert_eq!(finished.load(Ordering::SeqCst), 2);
+}
+
+/// Breaking out of `try_fold_and_stop` joins a worker configured to be joined.
+#[test]
+fn try_fold_and_stop_joins_worker() {
+    use std::ops::ControlFlow;
+    let dropped = Arc::new(AtomicBool::new(false));
+    let flag = DropFlag(dropped.clone());
+    let rah = ReadaheadBuilder::new(2)
+        .join_on_drop(None)
+        .spawn((0..).inspect(move |_| {
+            let _ = &flag;
+        }))
+        .unwrap();
+    let result = rah.try_fold_and_stop((), |(), i| {
+        if i == 3 {
+            ControlFlow::Break(i)
+        } else {
+            ControlFlow::Continue(())
+        }
+    });
+    assert_eq!(result, ControlFlow::Break(3));
+    assert!(dropped.load(Ordering::SeqCst));
+}
+
+/// The worker stops producing once the byte budget is used, and an item
+/// bigger than the whole budget still gets through.
+#[test]
+fn buffer_bytes_limits_production() {
+    let produced = Arc::new(AtomicUsize::new(0));
+    let produced_clone = produced.clone();
+    let mut rah = ReadaheadBuilder::unbounded()
+        .buffer_bytes(3000, |buf: &Vec<u8>| buf.len())
+        .spawn((0..100).map(move |i| {
+            produced_clone.fetch_add(1, Ordering::SeqCst);
+            vec![0u8; if i == 50 { 10_000 } else { 1000 }]
+        }))
+        .unwrap();
+    sleep(Duration::from_millis(50));
+    // Three are buffered, and the fourth is waiting to be sent.
+    assert_eq!(produced.load(Ordering::SeqCst), 4);
+    assert_eq!(rah.next().unwrap().len(), 1000);
+    assert_eq!(rah.map(|buf| buf.len()).sum::<usize>(), 98 * 1000 + 10_000);
+}
+
+#[test]
+fn lifo_delivers_newest_first() {
+    let mut rah = ReadaheadBuilder::new(4)
+        .order(DeliveryOrder::Lifo)
+        .spawn(0..10)
+        .unwrap();
+    rah.warm_up(4);
+    assert_eq!(rah.next(), Some(3));
+    let mut rest: Vec<u32> = rah.collect();
+    rest.sort_unstable();
+    assert_eq!(rest, [0, 1, 2, 4, 5, 6, 7, 8, 9]);
+}
+
+/// The completion handler isn't called if the consumer gives up early.
+#[test]
+fn on_complete_not_called_after_early_drop() {
+    let completed = Arc::new(AtomicBool::new(false));
+    let completed_clone = completed.clone();
+    let mut rah = ReadaheadBuilder::new(1)
+        .on_complete(move || completed_clone.store(true, Ordering::SeqCst))
+        .join_on_drop(None)
+        .spawn(0..100)
+        .unwrap();
+    assert_eq!(rah.next(), Some(0));
+    drop(rah);
+    assert!(!completed.load(Ordering::SeqCst));
+}
+
+/// The first item waits for the rest of its batch, and the rest of the batch
+/// is then ready straight away.
+#[test]
+fn min_deliver_batch_waits_for_batch() {
+    let mut rah = ReadaheadBuilder::new(10)
+        .min_deliver_batch(5)
+        .spawn((0..7).inspect(|_| sleep(Duration::from_millis(20))))
+        .unwrap();
+    let start = Instant::now();
+    assert_eq!(rah.next(), Some(0));
+    assert!(start.elapsed() >= Duration::from_millis(100));
+    let start = Instant::now();
+    assert_eq!(rah.by_ref().take(4).count(), 4);
+    assert!(start.elapsed() < Duration::from_millis(20));
+    assert_eq!(rah.collect::<Vec<_>>(), [5, 6]);
+}
+
+/// The factory runs on the worker thread, the same thread that produces every
+/// item, so per-thread setup done there applies to the whole stream.
+#[test]
+fn spawn_with_runs_factory_on_worker_thread() {
+    let factory_thread = Arc::new(Mutex::new(None));
+    let factory_thread_clone = factory_thread.clone();
+    let item_threads: Vec<thread::ThreadId> = ReadaheadBuilder::new(2)
+        .spawn_with(move || {
+            *factory_thread_clone.lock().unwrap() = Some(thread::current().id());
+            (0..5).map(|_| thread::current().id())
+        })
+        .unwrap()
+        .collect();
+    let factory_thread = factory_thread.lock().unwrap().unwrap();
+    assert_ne!(factory_thread, thread::current().id());
+    assert_eq!(item_threads, [factory_thread; 5]);
+}
+
+/// A consumer that nearly always waits for a slow producer is reported, once.
+#[test]
+fn on_degenerate_reports_empty_buffer() {
+    let warnings = Arc::new(Mutex::new(Vec::new()));
+    let warnings_clone = warnings.clone();
+    let rah = ReadaheadBuilder::new(2)
+        .on_degenerate(move |warning| warnings_clone.lock().unwrap().push(warning.to_owned()))
+        .spawn((0..150).inspect(|_| sleep(Duration::from_millis(2))))
+        .unwrap();
+    assert_eq!(rah.count(), 150);
+    let warnings = warnings.lock().unwrap();
+    assert_eq!(warnings.len(), 1, "{:?}", warnings);
+    assert!(warnings[0].contains("buffer was empty"), "{}", warnings[0]);
+}
+
+/// A rendezvous channel can't hold a batch, so all items are still delivered.
+#[test]
+fn min_deliver_batch_with_zero_buffer() {
+    let rah = ReadaheadBuilder::new(0)
+        .min_deliver_batch(4)
+        .spawn(0..100)
+        .unwrap();
+    assert_eq!(rah.collect::<Vec<_>>(), (0..100).collect::<Vec<_>>());
+}
+
+#[test]
+fn deterministic_parallel_map_runs_inline_in_order() {
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let calls2 = calls.clone();
+    let main_thread = thread::current().id();
+    let r = ReadaheadBuilder::new(2)
+        .deterministic(true)
+        .spawn_map_parallel_chunks(0..7u32, 3, 4, move |chunk: Vec<u32>| {
+            assert_eq!(thread::current().id(), main_thread);
+            calls2.lock().unwrap().push(chunk[0]);
+            chunk.iter().map(|i| i + 1).collect()
+        })
+        .unwrap();
+    let v: Vec<Vec<u32>> = r.collect();
+    assert_eq!(v, [vec![1, 2, 3], vec![4, 5, 6], vec![7]]);
+    assert_eq!(*calls.lock().unwrap(), [0, 3, 6]);
+}
+
+#[test]
+fn pause_stops_production_until_resumed() {
+    let produced = Arc::new(AtomicUsize::new(0));
+    let produced2 = produced.clone();
+    let mut r = Readahead::new(
+        (0..100).inspect(move |_| {
+            produced2.fetch_add(1, Ordering::SeqCst);
+        }),
+        4,
+    );
+    r.warm_up(4);
+    r.pause();
+    assert!(r.is_paused());
+    for i in 0..4 {
+        assert_eq!(r.next(), Some(i));
+    }
+    sleep(Duration::from_millis(100));
+    // The worker may have been inside, or about to send, one more item or so
+    // when it was paused, but no more.
+    let while_paused = produced.load(Ordering::SeqCst);
+    assert!(while_paused <= 6, "produced {} while paused", while_paused);
+    sleep(Duration::from_millis(100));
+    assert_eq!(produced.load(Ordering::SeqCst), while_paused);
+    r.resume();
+    assert!(!r.is_paused());
+    assert_eq!(r.count(), 96);
+}
+
+#[test]
+fn drop_while_paused_stops_worker() {
+    let r = ReadaheadBuilder::new(2)
+        .join_on_drop(None)
+        .spawn(0..)
+        .unwrap();
+    r.pause();
+    drop(r);
+}
+
+#[test]
+fn throttle_on_lag_delivers_everything_to_slow_consumer() {
+    let r = ReadaheadBuilder::new(2)
+        .throttle_on_lag(true)
+        .spawn(0..20)
+        .unwrap();
+    let v: Vec<i32> = r.inspect(|_| sleep(Duration::from_millis(2))).collect();
+    assert_eq!(v, (0..20).collect::<Vec<_>>());
+}
+
+#[test]
+fn for_each_with_worker_joins_and_reports_success() {
+    let finished = Arc::new(AtomicBool::new(false));
+    let finished2 = finished.clone();
+    let r = ReadaheadBuilder::new(2)
+        .join_on_drop(None)
+        .on_complete(move || finished2.store(true, Ordering::SeqCst))
+        .spawn(0..10)
+        .unwrap();
+    let mut sum = 0;
+    assert!(r.for_each_with_worker(|i| sum += i).is_ok());
+    assert_eq!(sum, 45);
+    assert!(finished.load(Ordering::SeqCst));
+}
+
+#[test]
+#[should_panic(expected = "consumer failed")]
+fn for_each_with_worker_doesnt_catch_consumer_panic() {
+    let _ = Readahead::new(0..10, 2).for_each_with_worker(|_| panic!("consumer failed"));
+}
+
+#[test]
+fn adaptive_buffer_grows_when_found_empty() {
+    let source = (0..100).inspect(|&i| {
+        if i < 5 {
+            sleep(Duration::from_millis(10));
+        }
+    });
+    let mut r = ReadaheadBuilder::new(0)
+        .adaptive_buffer(2, 16)
+        .spawn(source)
+        .unwrap();
+    for i in 0..5 {
+        assert_eq!(r.next(), Some(i));
+    }
+    sleep(Duration::from_millis(100));
+    assert_eq!(r.stats().unwrap().high_water_mark(), 16);
+    assert_eq!(r.count(), 95);
+}
+
+#[test]
+fn adaptive_buffer_stays_small_when_not_empty() {
+    let mut r = ReadaheadBuilder::new(0)
+        .adaptive_buffer(2, 16)
+        .spawn(0..100)
+        .unwrap();
+    sleep(Duration::from_millis(100));
+    assert_eq!(r.stats().unwrap().high_water_mark(), 2);
+    assert_eq!(r.next(), Some(0));
+    sleep(Duration::from_millis(100));
+    assert_eq!(r.stats().unwrap().high_water_mark(), 2);
+}
+
+/// A batch bigger than the adaptive buffer's current size is cut down to fit.
+#[test]
+fn adaptive_buffer_with_min_deliver_batch() {
+    let rah = ReadaheadBuilder::new(0)
+        .adaptive_buffer(2, 64)
+        .min_deliver_batch(16)
+        .spawn(0..100)
+        .unwrap();
+    assert_eq!(rah.collect::<Vec<_>>(), (0..100).collect::<Vec<_>>());
+}
+
+/// The worker blocks once the buffered items, counting their heap memory,
+/// reach the cap.
+#[test]
+fn max_memory_blocks_worker_at_byte_cap() {
+    let item_size = std::mem::size_of::<Vec<u8>>() + 1000;
+    let produced = Arc::new(AtomicUsize::new(0));
+    let produced_clone = produced.clone();
+    let mut r = ReadaheadBuilder::new(100)
+        .buffer_bytes(usize::MAX, |buf: &Vec<u8>| buf.len())
+        .max_memory(3 * item_size)
+        .spawn((0..20).map(move |_| {
+            produced_clone.fetch_add(1, Ordering::SeqCst);
+            vec![0u8; 1000]
+        }))
+        .unwrap();
+    // Three items fit; the worker makes a fourth, and waits for room for it.
+    while produced.load(Ordering::SeqCst) < 4 {
+        thread::yield_now();
+    }
+    sleep(Duration::from_millis(50));
+    assert_eq!(produced.load(Ordering::SeqCst), 4);
+    assert_eq!(r.buffered_len(), 3);
+    assert_eq!(r.next().map(|buf| buf.len()), Some(1000));
+    assert_eq!(r.count(), 19);
+}
+
+/// With spinning, items still arrive in order, and once the spins run out the
+/// worker blocks on a full buffer, including a rendezvous channel.
+#[test]
+fn spin_before_block_keeps_order_and_blocks() {
+    for buffer_size in [0, 4] {
+        let produced = Arc::new(AtomicUsize::new(0));
+        let produced_clone = produced.clone();
+        let mut r = ReadaheadBuilder::new(buffer_size)
+            .spin_before_block(1000)
+            .spawn((0..1000).inspect(move |_| {
+                produced_clone.fetch_add(1, Ordering::SeqCst);
+            }))
+            .unwrap();
+        // The buffer fills, and the worker makes one more item and waits to
+        // send it.
+        while produced.load(Ordering::SeqCst) < buffer_size + 1 {
+            thread::yield_now();
+        }
+        sleep(Duration::from_millis(50));
+        assert_eq!(produced.load(Ordering::SeqCst), buffer_size + 1);
+        assert_eq!(r.next(), Some(0));
+        assert_eq!(r.collect::<Vec<_>>(), (1..1000).collect::<Vec<_>>());
+    }
+}
+
+#[test]
+fn max_duration_stops_unending_source() {
+    let clock = ManualClock::new();
+    let completed = Arc::new(AtomicBool::new(false));
+    let completed2 = completed.clone();
+    let mut r = ReadaheadBuilder::new(2)
+        .clock(clock.clone())
+        .max_duration(Duration::from_secs(3600))
+        .on_complete(move || completed2.store(true, Ordering::SeqCst))
+        .spawn(0..)
+        .unwrap();
+    assert_eq!(r.next(), Some(0));
+    assert_eq!(r.next(), Some(1));
+    clock.advance(Duration::from_secs(3600));
+    // The items already made, and perhaps the one being made, are still
+    // delivered.
+    let rest: Vec<u32> = r.by_ref().collect();
+    assert!(rest.len() <= 4, "{:?}", rest);
+    assert_eq!(rest, (2..2 + rest.len() as u32).collect::<Vec<_>>());
+    assert!(!r.source_ended());
+    assert!(!completed.load(Ordering::SeqCst));
+}
+
+#[test]
+fn warn_slow_reports_slow_item() {
+    let clock = ManualClock::new();
+    let worker_clock = clock.clone();
+    let slow = Arc::new(Mutex::new(Vec::new()));
+    let slow_clone = slow.clone();
+    let items: Vec<u32> = ReadaheadBuilder::new(2)
+        .clock(clock)
+        .warn_slow(Duration::from_secs(1), move |index, elapsed| {
+            slow_clone.lock().unwrap().push((index, elapsed))
+        })
+        .spawn((0..4).inspect(move |&i| {
+            if i == 2 {
+                worker_clock.sleep(Duration::from_secs(5));
+            }
+        }))
+        .unwrap()
+        .collect();
+    assert_eq!(items, [0, 1, 2, 3]);
+    let slow = slow.lock().unwrap();
+    assert_eq!(slow.len(), 1, "{:?}", slow);
+    assert_eq!(slow[0].0, 2);
+    assert!(slow[0].1 >= Duration::from_secs(5));
+}
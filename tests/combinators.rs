@@ -0,0 +1,502 @@
+// Copyright 2021 Martin Pool
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Tests for combinators that run on the worker thread.
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, sleep, ThreadId};
+use std::time::Duration;
+
+use readahead_iterator::{IntoReadahead, IntoReadaheadPairs, IntoReadaheadResults};
+
+/// Returns the numbers `0..n`, and a counter of how many have been produced.
+fn counted(n: usize) -> (impl Iterator<Item = usize> + Send, Arc<AtomicUsize>) {
+    let produced = Arc::new(AtomicUsize::new(0));
+    let produced_clone = produced.clone();
+    let iter = (0..n).inspect(move |_| {
+        produced_clone.fetch_add(1, Ordering::SeqCst);
+    });
+    (iter, produced)
+}
+
+/// The worker stops pulling from the source at the first `None`.
+#[test]
+fn map_while_stops_producing() {
+    let (source, produced) = counted(100);
+    let v: Vec<usize> = source
+        .readahead_map_while(|i| if i < 5 { Some(i * 2) } else { None }, 50)
+        .collect();
+    assert_eq!(v, [0, 2, 4, 6, 8]);
+    assert_eq!(produced.load(Ordering::SeqCst), 6);
+}
+
+/// Zipping stops at the shorter side, and the longer worker stops when the
+/// result is dropped.
+#[test]
+fn zip_stops_at_shorter() {
+    let (long, produced) = counted(1_000_000);
+    let v: Vec<(usize, usize)> = (0..3).readahead_zip(long, 4).collect();
+    assert_eq!(v, [(0, 0), (1, 1), (2, 2)]);
+    // Give the long worker a chance to notice the channel is closed.
+    sleep(Duration::from_millis(50));
+    let after_drop = produced.load(Ordering::SeqCst);
+    sleep(Duration::from_millis(50));
+    assert_eq!(produced.load(Ordering::SeqCst), after_drop);
+    assert!(after_drop < 1000);
+}
+
+/// Once the shorter side ends, the longer worker stops and drops its source,
+/// even though the zip is still alive.
+#[test]
+fn zip_stops_longer_worker_at_end() {
+    let dropped = Arc::new(AtomicBool::new(false));
+    let on_drop = SetOnDrop(dropped.clone());
+    let long = (0..).inspect(move |_: &usize| {
+        let _ = &on_drop;
+    });
+    let mut zip = (0..3).readahead_zip(long, 4);
+    assert_eq!(zip.by_ref().collect::<Vec<_>>(), [(0, 0), (1, 1), (2, 2)]);
+    for _ in 0..100 {
+        if dropped.load(Ordering::SeqCst) {
+            break;
+        }
+        sleep(Duration::from_millis(10));
+    }
+    assert!(dropped.load(Ordering::SeqCst));
+    assert_eq!(zip.next(), None);
+
+    struct SetOnDrop(Arc<AtomicBool>);
+
+    impl Drop for SetOnDrop {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Each stage of a pipeline runs on its own thread.
+#[test]
+fn pipeline_stages_on_separate_threads() {
+    use readahead_iterator::readahead_pipeline;
+
+    let thread_id = || thread::current().id();
+    let v: Vec<_> = readahead_pipeline!(
+        0..10,
+        (2, move |i| (i, thread_id())),
+        (2, move |(i, first)| (i, first, thread_id())),
+    )
+    .collect();
+    assert_eq!(v.len(), 10);
+    for (i, (j, first, second)) in v.into_iter().enumerate() {
+        assert_eq!(i, j);
+        assert_ne!(first, second);
+        assert_ne!(first, thread_id());
+        assert_ne!(second, thread_id());
+    }
+}
+
+/// Filtering on the worker gives the same results as filtering on the consumer.
+#[test]
+fn filter_matches_consumer_side_filter() {
+    let is_interesting = |s: &String| s.contains('7');
+    let items = || (0..1000).map(|i| i.to_string());
+    let on_worker: Vec<String> = items().readahead_filter(is_interesting, 8).collect();
+    let on_consumer: Vec<String> = items().readahead(8).filter(is_interesting).collect();
+    assert_eq!(on_worker, on_consumer);
+    assert_eq!(on_worker.len(), 271);
+}
+
+/// The worker produces no more than the requested number of items.
+#[test]
+fn take_limits_production() {
+    let (source, produced) = counted(1000);
+    let mut rah = source.readahead_take(5, 100);
+    assert_eq!(rah.next(), Some(0));
+    // Give the worker time to run ahead as far as it can.
+    sleep(Duration::from_millis(50));
+    assert_eq!(produced.load(Ordering::SeqCst), 5);
+    assert_eq!(rah.count(), 4);
+}
+
+/// Grouping handles empty input, and flushes the final group.
+#[test]
+fn group_by_edges() {
+    let empty: Vec<(u32, Vec<u32>)> = (0..0).readahead_group_by(|i| *i, 2).collect();
+    assert!(empty.is_empty());
+    let all_same: Vec<(u32, Vec<u32>)> = vec![7, 7, 7]
+        .into_iter()
+        .readahead_group_by(|i| *i, 2)
+        .collect();
+    assert_eq!(all_same, [(7, vec![7, 7, 7])]);
+}
+
+/// Dropping one side of a partition doesn't stop the other.
+#[test]
+fn partition_survives_dropped_side() {
+    let (small, large) = (0..100u32).readahead_partition(|i| *i < 10, 1);
+    drop(small);
+    assert_eq!(large.count(), 90);
+}
+
+#[test]
+fn unzip_survives_dropped_side() {
+    let (numbers, squares) = (0..100u32).map(|i| (i, i * i)).readahead_unzip(1);
+    drop(numbers);
+    assert_eq!(squares.last(), Some(99 * 99));
+}
+
+/// The context is created once, on the worker; it needn't be `Send`.
+#[test]
+fn with_context_runs_init_once_on_worker() {
+    use std::rc::Rc;
+    let inits = Arc::new(AtomicUsize::new(0));
+    let inits_clone = inits.clone();
+    let v: Vec<usize> = (0..5)
+        .readahead_with_context(
+            move || {
+                inits_clone.fetch_add(1, Ordering::SeqCst);
+                Rc::new(10)
+            },
+            |ctx, i| **ctx + i,
+            2,
+        )
+        .collect();
+    assert_eq!(v, [10, 11, 12, 13, 14]);
+    assert_eq!(inits.load(Ordering::SeqCst), 1);
+}
+
+/// Disorder beyond the window isn't fully sorted, but nothing is lost.
+#[test]
+fn sorted_is_best_effort_past_window() {
+    let v: Vec<u32> = vec![9, 0, 1, 2, 3, 4, 5]
+        .into_iter()
+        .readahead_sorted(2)
+        .collect();
+    assert_eq!(v, [0, 1, 2, 3, 4, 5, 9]);
+    let v: Vec<u32> = vec![5, 4, 3, 2, 1, 0]
+        .into_iter()
+        .readahead_sorted(1)
+        .collect();
+    assert_ne!(v, [0, 1, 2, 3, 4, 5]);
+    assert_eq!(v.len(), 6);
+}
+
+#[test]
+fn windows_of_short_source() {
+    assert_eq!((0..2).readahead_windows(3, 2).count(), 0);
+    let v: Vec<Vec<u32>> = (0..3).readahead_windows(3, 2).collect();
+    assert_eq!(v, [vec![0, 1, 2]]);
+}
+
+/// Acks can arrive out of order, and the worker waits for them all.
+#[test]
+fn acks_follow_drop_order() {
+    use std::sync::mpsc::channel;
+    let (sender, receiver) = channel();
+    let mut rah = (0..3).readahead_acked(move |i| sender.send(i).unwrap(), 1);
+    let held = rah.next().unwrap();
+    let rest: Vec<u32> = rah.by_ref().map(|ack| *ack).collect();
+    assert_eq!(rest, [1, 2]);
+    drop(held);
+    let acks: Vec<usize> = receiver.iter().collect();
+    assert_eq!(acks, [1, 2, 0]);
+}
+
+#[derive(Debug, PartialEq)]
+enum PipelineError {
+    Parse(String),
+}
+
+impl From<std::num::ParseIntError> for PipelineError {
+    fn from(err: std::num::ParseIntError) -> Self {
+        PipelineError::Parse(err.to_string())
+    }
+}
+
+#[test]
+fn map_err_converts_errors() {
+    let v: Vec<Result<u32, PipelineError>> = vec!["1", "x"]
+        .into_iter()
+        .map(str::parse::<u32>)
+        .readahead_map_err(PipelineError::from, 2)
+        .collect();
+    assert_eq!(v[0], Ok(1));
+    assert!(matches!(&v[1], Err(PipelineError::Parse(_))));
+}
+
+/// A batch is closed when its window elapses, even if more items follow.
+#[test]
+fn coalesce_time_flushes_on_window() {
+    let batches: Vec<Vec<u32>> = (0..4)
+        .inspect(|i| {
+            if *i == 2 {
+                sleep(Duration::from_millis(200));
+            }
+        })
+        .readahead_coalesce_time(Duration::from_millis(50), 10, 2)
+        .collect();
+    assert_eq!(batches, [vec![0, 1], vec![2, 3]]);
+}
+
+/// With a slow consumer, the buffer fills up behind it.
+#[test]
+fn with_depth_sees_buffer_fill() {
+    let mut r = (0..10).readahead_with_depth(4);
+    sleep(Duration::from_millis(100));
+    let depths: Vec<usize> = r.by_ref().take(5).map(|(depth, _)| depth).collect();
+    assert_eq!(depths, [0, 1, 2, 3, 4]);
+    assert_eq!(r.map(|(_, item)| item).collect::<Vec<_>>(), [5, 6, 7, 8, 9]);
+}
+
+/// Items buffered before the shutdown are still delivered, in order.
+#[test]
+fn shutdown_drains_buffered_items() {
+    let (rah, shutdown) = (0..).readahead_with_shutdown(4);
+    let stopper = std::thread::spawn(move || shutdown.stop());
+    stopper.join().unwrap();
+    let v: Vec<u32> = rah.collect();
+    assert!(v.len() <= 5);
+    assert!(v.iter().copied().eq(0..v.len() as u32));
+}
+
+/// A consumer that stops early gets no summary.
+#[test]
+fn summary_only_at_end() {
+    let mut rah = (0..100u32).readahead_with_summary(0, |sum, i| *sum += i, 4);
+    assert_eq!(rah.nth(10), Some(10));
+    assert_eq!(rah.take_summary(), None);
+    assert_eq!(rah.last(), Some(99));
+    let mut rah = (0..100u32).readahead_with_summary(0, |sum, i| *sum += i, 4);
+    rah.by_ref().for_each(drop);
+    assert_eq!(rah.take_summary(), Some(4950));
+    assert_eq!(rah.take_summary(), None);
+}
+
+#[test]
+fn distinct_keeps_first_of_each() {
+    let v: Vec<u32> = (0..1000).map(|i| i % 7).readahead_distinct(2).collect();
+    assert_eq!(v, [0, 1, 2, 3, 4, 5, 6]);
+}
+
+#[test]
+fn retry_gives_up_after_max_attempts() {
+    let source = vec![
+        Err("a"),
+        Err("b"),
+        Err("c"),
+        Ok(1),
+        Err("fatal"),
+        Ok(2),
+        Err("d"),
+    ];
+    let v: Vec<_> = source
+        .into_iter()
+        .readahead_retry(2, |err| *err != "fatal", 4)
+        .collect();
+    // "a" is retried and "b" passed on after two attempts; "c" is retried,
+    // "fatal" isn't retried, and "d" is passed on when the source ends.
+    assert_eq!(v, [Err("b"), Ok(1), Err("fatal"), Ok(2), Err("d")]);
+}
+
+/// The second source starts producing while the first is consumed.
+#[test]
+fn chain_prefetches_second_source() {
+    let (second, produced) = counted(10);
+    let mut v = (100..103).readahead_chain(second, 4);
+    assert_eq!(v.next(), Some(100));
+    sleep(Duration::from_millis(100));
+    assert!(produced.load(Ordering::SeqCst) >= 4);
+    assert_eq!(
+        v.collect::<Vec<_>>(),
+        [101, 102, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9]
+    );
+}
+
+#[test]
+fn window_lookahead_boundaries() {
+    let mut window = (0..3).readahead_window(1, 2);
+    assert_eq!(window.ahead(1), Some(&0));
+    assert_eq!(window.ahead(3), None);
+    assert_eq!(window.advance(), Some(&0));
+    assert_eq!(window.ahead(2), Some(&2));
+    assert_eq!(window.advance(), Some(&1));
+    assert_eq!(window.behind(1), Some(&0));
+    assert_eq!(window.ahead(1), Some(&2));
+    assert_eq!(window.ahead(2), None);
+    assert_eq!(window.advance(), Some(&2));
+    assert_eq!(window.advance(), None);
+    assert_eq!(window.behind(1), Some(&2));
+}
+
+/// Chunks come back in order even when later ones finish first.
+#[test]
+fn map_parallel_chunks_keeps_order() {
+    let v: Vec<Vec<u32>> = (0..10u32)
+        .readahead_map_parallel_chunks(
+            3,
+            3,
+            |chunk| {
+                // Earlier chunks take longer.
+                sleep(Duration::from_millis(30 - 3 * chunk[0] as u64));
+                chunk.iter().map(|i| i * 10).collect()
+            },
+            2,
+        )
+        .collect();
+    assert_eq!(
+        v,
+        [
+            vec![0, 10, 20],
+            vec![30, 40, 50],
+            vec![60, 70, 80],
+            vec![90]
+        ]
+    );
+}
+
+#[test]
+#[should_panic(expected = "bad chunk")]
+fn map_parallel_chunks_propagates_panic() {
+    (0..100u32)
+        .readahead_map_parallel_chunks(
+            10,
+            2,
+            |chunk| {
+                assert!(chunk[0] != 50, "bad chunk");
+                chunk
+            },
+            2,
+        )
+        .for_each(drop);
+}
+
+#[test]
+fn with_name_fn_skips_empty_source() {
+    let v: Vec<u32> = std::iter::empty()
+        .readahead_with_name_fn(4, |_: &u32| panic!("name_fn called"))
+        .collect();
+    assert!(v.is_empty());
+}
+
+#[test]
+fn fork_continues_after_one_output_drops() {
+    let mut outputs = (0..50u32).readahead_fork(3, 2);
+    assert_eq!(outputs.len(), 3);
+    drop(outputs.remove(1));
+    let mut first = outputs.remove(0);
+    let mut second = outputs.remove(0);
+    for i in 0..50 {
+        assert_eq!(first.next(), Some(i));
+        assert_eq!(second.next(), Some(i));
+    }
+    assert_eq!(first.next(), None);
+    assert_eq!(second.next(), None);
+}
+
+/// Records which thread it's dropped on.
+struct DropsOn(usize, Arc<Mutex<Vec<(usize, ThreadId)>>>);
+
+impl Drop for DropsOn {
+    fn drop(&mut self) {
+        self.1
+            .lock()
+            .unwrap()
+            .push((self.0, thread::current().id()));
+    }
+}
+
+/// Skipped items are dropped on the worker, without crossing to the consumer.
+#[test]
+fn skip_while_drops_skipped_items_on_worker() {
+    let drops = Arc::new(Mutex::new(Vec::new()));
+    let source_drops = drops.clone();
+    let consumer = thread::current().id();
+    let kept: Vec<usize> = (0..6)
+        .map(move |i| DropsOn(i, source_drops.clone()))
+        .readahead_skip_while(|item| item.0 < 4, 4)
+        .map(|item| item.0)
+        .collect();
+    assert_eq!(kept, [4, 5]);
+    let drops = drops.lock().unwrap();
+    for &(i, thread) in drops.iter() {
+        assert_eq!(
+            thread == consumer,
+            i >= 4,
+            "item {} dropped on wrong thread",
+            i
+        );
+    }
+    assert_eq!(drops.len(), 6);
+}
+
+#[test]
+fn interleave_reads_sources_of_different_lengths() {
+    use readahead_iterator::readahead_interleave;
+
+    let sources: Vec<Box<dyn Iterator<Item = usize> + Send>> = vec![
+        Box::new(0..1),
+        Box::new(std::iter::empty()),
+        Box::new((10..14).inspect(|_| sleep(Duration::from_millis(5)))),
+    ];
+    let v: Vec<usize> = readahead_interleave(sources, 2).collect();
+    assert_eq!(v, [0, 10, 11, 12, 13]);
+}
+
+#[test]
+fn shared_forks_items_that_arent_clone() {
+    struct NotClone(usize);
+
+    let mut outputs = (0..3)
+        .map(NotClone)
+        .readahead_shared(2)
+        .readahead_fork(2, 4);
+    let second: Vec<usize> = outputs.pop().unwrap().map(|item| item.0).collect();
+    let first: Vec<usize> = outputs.pop().unwrap().map(|item| item.0).collect();
+    assert_eq!(first, [0, 1, 2]);
+    assert_eq!(second, first);
+}
+
+/// Indices are source positions, even when the consumer skips items.
+#[test]
+fn map_with_index_passes_source_positions() {
+    let mut r = (100..200).readahead_map_with_index(|i, item| (i, item), 8);
+    assert_eq!(r.next(), Some((0, 100)));
+    assert_eq!(r.nth(10), Some((11, 111)));
+    let rest: Vec<(usize, u32)> = r.collect();
+    assert_eq!(rest.len(), 88);
+    for (j, &(i, item)) in rest.iter().enumerate() {
+        assert_eq!(i, j + 12);
+        assert_eq!(item as usize, i + 100);
+    }
+}
+
+/// Each item delivered is the greatest of those buffered at the time, and the
+/// buffer stays bounded.
+#[test]
+fn priority_delivers_greatest_buffered() {
+    let mut r = [3, 9, 1, 7, 5, 8, 2].iter().copied().readahead_priority(4);
+    r.warm_up(4);
+    sleep(Duration::from_millis(50));
+    assert!(r.stats().unwrap().high_water_mark() <= 4);
+    assert_eq!(r.next(), Some(9));
+    let rest: Vec<i32> = r.collect();
+    let mut sorted = rest.clone();
+    sorted.sort_unstable();
+    assert_eq!(sorted, [1, 2, 3, 5, 7, 8]);
+}
+
+#[test]
+fn collect_errors_keeps_everything_in_order() {
+    let (values, errors) = (0..100u32)
+        .map(|i| if i % 7 == 0 { Err(i) } else { Ok(i * 2) })
+        .readahead_collect_errors(4);
+    assert_eq!(values.len(), 85);
+    assert_eq!(values[..3], [2, 4, 6]);
+    assert_eq!(errors, (0..100).step_by(7).collect::<Vec<_>>());
+}
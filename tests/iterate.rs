@@ -6,10 +6,14 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use std::thread::sleep;
+use std::iter;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::sync_channel;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, sleep};
 use std::time::Duration;
 
-use readahead_iterator::Readahead;
+use readahead_iterator::{IntoReadahead, Readahead, ReadaheadBuilder};
 
 /// A lot like examples/sleepy, but with minimal sleeps.
 #[test]
@@ -27,6 +31,7 @@ fn iterate_integers() {
 
 /// Continuing to read after the enclosed function ends gets more Nones.
 #[test]
+#[allow(clippy::map_identity)]
 fn read_past_end() {
     let mut rah = Readahead::new(
         (0..10)
@@ -43,3 +48,571 @@ fn read_past_end() {
         }
     }
 }
+
+/// Dropping the consumer stops the worker, even if the input never ends.
+#[test]
+fn unbounded_input() {
+    let v: Vec<usize> = Readahead::new(0.., 10).take(5).collect();
+    assert_eq!(v, [0, 1, 2, 3, 4]);
+}
+
+/// The worker of an endless cycle stops when the consumer is dropped.
+#[test]
+fn cycle_stops_when_dropped() {
+    let produced = Arc::new(AtomicUsize::new(0));
+    let counter = produced.clone();
+    let mut rah = (0..3)
+        .inspect(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        })
+        .readahead_cycle(4);
+    assert_eq!(rah.by_ref().take(10).count(), 10);
+    drop(rah);
+    // The worker may finish the item it was making when it was dropped.
+    sleep(Duration::from_millis(50));
+    let count = produced.load(Ordering::SeqCst);
+    sleep(Duration::from_millis(50));
+    assert_eq!(produced.load(Ordering::SeqCst), count);
+}
+
+/// `has_more` becomes false once the worker finishes and its items are consumed.
+#[test]
+fn has_more_after_consuming_everything() {
+    let mut rah = Readahead::new(0..3, 10);
+    assert_eq!(rah.by_ref().take(3).count(), 3);
+    for _ in 0..1000 {
+        if !rah.has_more() {
+            break;
+        }
+        sleep(Duration::from_millis(1));
+    }
+    assert!(!rah.has_more());
+    assert_eq!(rah.next(), None);
+    assert!(!rah.has_more());
+}
+
+/// The producer behind `from_channel` hangs up without sending the final `None`.
+#[test]
+#[should_panic(expected = "recv of iterator value failed")]
+fn sender_exits_unexpectedly() {
+    let (sender, receiver) = sync_channel(4);
+    let producer = thread::spawn(move || {
+        sender.send(Some(1)).unwrap();
+    });
+    let mut rah = Readahead::from_channel(receiver);
+    producer.join().unwrap();
+    assert_eq!(rah.next(), Some(1));
+    rah.next();
+}
+
+/// `readahead_lines` gives the same lines as `BufRead::lines`.
+#[test]
+fn lines_match_bufread() {
+    use std::io::{BufRead, Cursor};
+
+    use readahead_iterator::readahead_lines;
+
+    let text = "first\n\nthird\r\nlast without newline";
+    let expected: Vec<String> = Cursor::new(text).lines().map(Result::unwrap).collect();
+    let actual: Vec<String> = readahead_lines(Cursor::new(text), 2)
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(actual, expected);
+}
+
+/// The reader gives back every byte, even with a single recycled buffer, and
+/// then the source's error.
+#[test]
+fn buffered_reader_recycles_and_reports_errors() {
+    use std::io::{self, Cursor, Read};
+
+    use readahead_iterator::readahead_buffered_reader;
+
+    let data: Vec<u8> = (0..=255).cycle().take(10_000).collect();
+    let failing = Cursor::new(data.clone()).chain(FailingReader);
+    let mut reader = readahead_buffered_reader(failing, 7, 1);
+    let mut actual = vec![0; data.len()];
+    reader.read_exact(&mut actual).unwrap();
+    assert_eq!(actual, data);
+    assert_eq!(
+        reader.read(&mut [0; 10]).unwrap_err().kind(),
+        io::ErrorKind::Other
+    );
+    assert_eq!(reader.read(&mut [0; 10]).unwrap(), 0);
+
+    struct FailingReader;
+
+    impl Read for FailingReader {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::other("failed"))
+        }
+    }
+}
+
+/// An async source is read ahead by blocking on each item on the worker.
+#[test]
+fn from_generator_blocks_on_async_source() {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::task::{Context, Poll, Wake, Waker};
+
+    let mut source = AsyncCounter { next: 0, end: 5 };
+    let items: Vec<u32> = Readahead::from_generator(move || block_on(source.next()), 2).collect();
+    assert_eq!(items, [0, 1, 2, 3, 4]);
+
+    struct AsyncCounter {
+        next: u32,
+        end: u32,
+    }
+
+    impl AsyncCounter {
+        async fn next(&mut self) -> Option<u32> {
+            YieldOnce(false).await;
+            if self.next == self.end {
+                return None;
+            }
+            self.next += 1;
+            Some(self.next - 1)
+        }
+    }
+
+    /// Returns `Pending` once, as a real source would while waiting.
+    struct YieldOnce(bool);
+
+    impl Future for YieldOnce {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            if self.0 {
+                Poll::Ready(())
+            } else {
+                self.0 = true;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+    }
+
+    /// Stands in for a runtime handle's `block_on`.
+    fn block_on<F: Future>(future: F) -> F::Output {
+        struct Unpark(thread::Thread);
+
+        impl Wake for Unpark {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+
+        let waker = Waker::from(Arc::new(Unpark(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut future = Box::pin(future);
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+}
+
+/// A `Readahead` can be sent to another thread and iterated there, as rayon's
+/// `par_bridge` requires.
+#[test]
+fn readahead_is_send() {
+    fn assert_send<T: Send>(t: T) -> T {
+        t
+    }
+    let rah = assert_send((0..10).readahead(4));
+    let sum = thread::spawn(move || rah.sum::<u32>()).join().unwrap();
+    assert_eq!(sum, 45);
+}
+
+/// A decoder wrapped around the source runs on the worker thread, not in the
+/// consumer's reads.
+#[test]
+fn buffered_reader_decodes_on_worker() {
+    use std::io::{self, Cursor, Read};
+
+    use readahead_iterator::readahead_buffered_reader;
+
+    let consumer = thread::current().id();
+    let decoder = Uppercase {
+        inner: Cursor::new("some compressed text".repeat(100)),
+        threads: Arc::new(Mutex::new(Vec::new())),
+    };
+    let threads = decoder.threads.clone();
+    let mut decoded = String::new();
+    readahead_buffered_reader(decoder, 64, 2)
+        .read_to_string(&mut decoded)
+        .unwrap();
+    assert_eq!(decoded, "SOME COMPRESSED TEXT".repeat(100));
+    let threads = threads.lock().unwrap();
+    assert!(!threads.is_empty());
+    assert!(threads.iter().all(|&id| id != consumer));
+
+    /// Stands in for a decompressor, recording which threads it runs on.
+    struct Uppercase {
+        inner: Cursor<String>,
+        threads: Arc<Mutex<Vec<thread::ThreadId>>>,
+    }
+
+    impl Read for Uppercase {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.threads.lock().unwrap().push(thread::current().id());
+            let len = self.inner.read(buf)?;
+            buf[..len].make_ascii_uppercase();
+            Ok(len)
+        }
+    }
+}
+
+/// After warming up, the buffered items are ready without waiting for the slow
+/// producer.
+#[test]
+fn warm_up_fills_buffer() {
+    let mut rah = Readahead::new((0..10).inspect(|_| sleep(Duration::from_millis(20))), 5);
+    rah.warm_up(5);
+    let start = std::time::Instant::now();
+    assert_eq!(rah.by_ref().take(5).count(), 5);
+    assert!(start.elapsed() < Duration::from_millis(50));
+    // Warming up past the end of a short stream returns when the worker
+    // finishes.
+    let rah = Readahead::new(0..2, 10);
+    rah.warm_up(10);
+    assert_eq!(rah.count(), 2);
+}
+
+/// Peeking at the end of the stream behaves like the plain `Readahead`.
+#[test]
+fn peek_at_end() {
+    use readahead_iterator::IntoReadahead;
+
+    let mut rah = (0..1).readahead_peekable(4);
+    assert_eq!(rah.peek(), Some(&0));
+    if let Some(v) = rah.peek_mut() {
+        *v = 7;
+    }
+    assert_eq!(rah.next(), Some(7));
+    assert_eq!(rah.peek(), None);
+    assert_eq!(rah.next_if(|_| true), None);
+    assert!(!rah.has_more());
+    assert_eq!(rah.next(), None);
+    assert_eq!(rah.next(), None);
+}
+
+#[test]
+fn nth_matches_default() {
+    let mut rah = Readahead::new(0..10, 3);
+    assert_eq!(rah.nth(1), Some(1));
+    assert_eq!(rah.nth(3), Some(5));
+    assert_eq!(rah.nth(3), Some(9));
+    assert_eq!(rah.next(), None);
+    assert_eq!(rah.nth(100), None);
+    assert_eq!(Readahead::new(0..5, 2).nth(7), None);
+}
+
+/// Zero-sized items are counted correctly, and `Some(())` isn't mistaken for
+/// the end of the stream.
+#[test]
+fn zero_sized_items() {
+    assert_eq!(Readahead::new((0..1000).map(|_| ()), 3).count(), 1000);
+    assert_eq!(Readahead::new(iter::empty::<()>(), 3).count(), 0);
+
+    struct Unit;
+    let mut rah = Readahead::new(iter::repeat_with(|| Unit).take(2), 1);
+    assert!(rah.next().is_some());
+    assert!(rah.next().is_some());
+    assert!(rah.next().is_none());
+    assert!(!rah.has_more());
+}
+
+#[test]
+fn worker_is_alive_until_it_finishes() {
+    let (sender, receiver) = sync_channel(0);
+    let mut rah = Readahead::new(iter::from_fn(move || receiver.recv().ok()), 4);
+    assert!(rah.worker_is_alive());
+    sender.send(1).unwrap();
+    assert_eq!(rah.next(), Some(1));
+    assert!(rah.worker_is_alive());
+    drop(sender);
+    // The worker finishes just after sending the end of the stream.
+    assert_eq!(rah.next(), None);
+    sleep(Duration::from_millis(10));
+    assert!(!rah.worker_is_alive());
+}
+
+/// An endless generator stops once the consumer is dropped.
+#[test]
+fn repeat_with_stops_after_drop() {
+    let (sender, receiver) = sync_channel(100);
+    let mut rah = Readahead::repeat_with(move || sender.send(()).is_ok(), 2);
+    assert_eq!(rah.next(), Some(true));
+    drop(rah);
+    // Once the worker stops, the sender is dropped and the channel disconnects.
+    let calls = receiver.iter().count();
+    assert!(calls <= 5);
+}
+
+/// In debug builds, the worker reaching its own `Readahead` panics rather than
+/// deadlocking.
+#[cfg(debug_assertions)]
+#[test]
+fn worker_calling_own_next_panics() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use std::sync::{Arc, Mutex};
+
+    let slot: Arc<Mutex<Option<Readahead<()>>>> = Arc::new(Mutex::new(None));
+    let slot_clone = slot.clone();
+    let (start_sender, start) = sync_channel(0);
+    let (report, reported) = sync_channel(1);
+    let rah = Readahead::new(
+        iter::once_with(move || {
+            start.recv().unwrap();
+            let mut own = slot_clone.lock().unwrap().take().unwrap();
+            let result = catch_unwind(AssertUnwindSafe(|| own.next()));
+            report.send(result.is_err()).unwrap();
+        }),
+        1,
+    );
+    *slot.lock().unwrap() = Some(rah);
+    start_sender.send(()).unwrap();
+    assert!(reported.recv().unwrap());
+}
+
+/// Resetting waits for the old worker to stop and drop its inner iterator.
+#[test]
+fn reset_stops_old_worker() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    struct SetOnDrop(Arc<AtomicBool>);
+    impl Drop for SetOnDrop {
+        fn drop(&mut self) {
+            sleep(Duration::from_millis(50));
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    let dropped = Arc::new(AtomicBool::new(false));
+    let guard = SetOnDrop(dropped.clone());
+    let mut rah = Readahead::new(
+        (0..).inspect(move |_| {
+            let _ = &guard;
+        }),
+        1,
+    );
+    assert_eq!(rah.next(), Some(0));
+    rah.reset(10..13, 1);
+    assert!(dropped.load(Ordering::SeqCst));
+    assert_eq!(rah.count(), 3);
+}
+
+/// Small sources run on the consumer thread; large or unbounded ones don't.
+#[test]
+fn or_inline_depends_on_size() {
+    let consumer = thread::current().id();
+    let on_thread = |n: usize| {
+        (0..n)
+            .map(|_| thread::current().id())
+            .readahead_or_inline(4, 10)
+            .all(|id| id == consumer)
+    };
+    assert!(on_thread(5));
+    assert!(!on_thread(50));
+    assert!(!(0..)
+        .map(|_| thread::current().id())
+        .readahead_or_inline(4, 10)
+        .take(3)
+        .any(|id| id == consumer));
+}
+
+#[test]
+fn high_water_mark_stays_low_with_slow_producer() {
+    let source = (0..5).inspect(|_| sleep(Duration::from_millis(20)));
+    let mut r = Readahead::new(source, 10);
+    while r.next().is_some() {
+        assert!(r.stats().unwrap().high_water_mark() <= 2);
+    }
+    let (_sender, receiver) = sync_channel::<Option<u32>>(1);
+    assert!(Readahead::from_channel(receiver).stats().is_none());
+}
+
+#[test]
+fn buffered_len_counts_items_ready() {
+    let mut r = Readahead::new(0..3, 8);
+    r.warm_up(8);
+    assert_eq!(r.buffered_len(), 3);
+    r.next();
+    assert_eq!(r.buffered_len(), 2);
+    assert_eq!(r.by_ref().count(), 2);
+    assert_eq!(r.buffered_len(), 0);
+}
+
+#[test]
+fn pooled_items_are_recycled() {
+    use readahead_iterator::readahead_pooled;
+
+    let allocations = Arc::new(AtomicUsize::new(0));
+    let fill_allocations = allocations.clone();
+    let mut n = 0;
+    let rows = readahead_pooled(
+        3,
+        move |buf: &mut Vec<u8>| {
+            if buf.capacity() == 0 {
+                fill_allocations.fetch_add(1, Ordering::SeqCst);
+                buf.reserve(64);
+            }
+            n += 1;
+            buf.clear();
+            buf.push(n);
+            n <= 20
+        },
+        1,
+    );
+    let mut kept = Vec::new();
+    for (i, row) in rows.enumerate() {
+        assert_eq!(row[0] as usize, i + 1);
+        if i == 5 {
+            kept.push(row.into_inner());
+        }
+    }
+    assert_eq!(kept, [vec![6]]);
+    // At most the pool, and one more to replace the kept item.
+    assert!(allocations.load(Ordering::SeqCst) <= 4);
+}
+
+#[test]
+fn source_ended_before_buffer_drained() {
+    let mut r = Readahead::new(0..4, 8);
+    assert_eq!(r.next(), Some(0));
+    let mut waited = 0;
+    while !r.source_ended() {
+        sleep(Duration::from_millis(1));
+        waited += 1;
+        assert!(waited < 5000, "source never ended");
+    }
+    assert_eq!(r.collect::<Vec<_>>(), [1, 2, 3]);
+
+    // A source that's stopped early hasn't ended.
+    let mut r = Readahead::new(0.., 1);
+    assert_eq!(r.by_ref().take(2).count(), 2);
+    assert!(!r.source_ended());
+}
+
+#[test]
+fn files_are_read_in_order_with_errors_in_place() {
+    use std::path::PathBuf;
+
+    use readahead_iterator::readahead_files;
+
+    let mut paths: Vec<PathBuf> = std::fs::read_dir("src")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    paths.insert(3, PathBuf::from("src/missing.rs"));
+    let results: Vec<_> = readahead_files(paths.clone().into_iter(), 3, 2).collect();
+    assert_eq!(results.len(), paths.len());
+    for (path, result) in paths.iter().zip(results) {
+        match result {
+            Ok((read_path, contents)) => {
+                assert_eq!(&read_path, path);
+                assert_eq!(contents, std::fs::read(path).unwrap());
+            }
+            Err(err) => {
+                assert_eq!(path, &PathBuf::from("src/missing.rs"));
+                assert!(err.to_string().contains("src/missing.rs"));
+            }
+        }
+    }
+}
+
+#[test]
+fn skip_ahead_matches_consuming_items() {
+    let mut skipped = Readahead::new_resume(0..20, 4, 5);
+    let mut consumed = Readahead::new_resume(0..20, 4, 5);
+    assert_eq!(skipped.skip_ahead(7), 7);
+    for _ in 0..7 {
+        consumed.next();
+    }
+    assert_eq!(skipped.position(), consumed.position());
+    assert_eq!(skipped.items_yielded(), 7);
+    assert_eq!(skipped.skip_ahead(0), 0);
+    assert_eq!(skipped.collect::<Vec<_>>(), consumed.collect::<Vec<_>>());
+}
+
+#[test]
+fn into_drained_iter_takes_buffer_and_stops_worker() {
+    let mut r = Readahead::new(0..5, 4);
+    assert_eq!(r.next(), Some(0));
+    while !r.source_ended() {
+        thread::yield_now();
+    }
+    assert_eq!(r.into_drained_iter().collect::<Vec<_>>(), [1, 2, 3, 4]);
+
+    let produced = Arc::new(AtomicUsize::new(0));
+    let produced2 = produced.clone();
+    let mut r = ReadaheadBuilder::new(4)
+        .join_on_drop(None)
+        .spawn((0..).inspect(move |_| {
+            produced2.fetch_add(1, Ordering::SeqCst);
+        }))
+        .unwrap();
+    assert_eq!(r.next(), Some(0));
+    r.warm_up(4);
+    let rest: Vec<u32> = r.into_drained_iter().collect();
+    assert_eq!(rest, (1..1 + rest.len() as u32).collect::<Vec<_>>());
+    // The worker was joined, so it makes no more.
+    let count = produced.load(Ordering::SeqCst);
+    sleep(Duration::from_millis(50));
+    assert_eq!(produced.load(Ordering::SeqCst), count);
+
+    let disabled = ReadaheadBuilder::new(4).enabled(false).spawn(0..3).unwrap();
+    assert_eq!(disabled.into_drained_iter().count(), 0);
+}
+
+#[test]
+fn drain_timed_counts_items_and_time() {
+    let report =
+        Readahead::new((0..5).inspect(|_| sleep(Duration::from_millis(10))), 2).drain_timed();
+    assert_eq!(report.items(), 5);
+    assert!(report.elapsed() >= Duration::from_millis(40));
+    assert!(report.items_per_sec() > 0.0);
+    assert!(report.items_per_sec() <= 5.0 / 0.04);
+    assert!(report.high_water_mark() <= 2);
+}
+
+#[test]
+fn new_owning_drops_owner_on_worker() {
+    struct Owner {
+        words: Vec<String>,
+        dropped_on: Arc<Mutex<Option<thread::ThreadId>>>,
+    }
+    impl Drop for Owner {
+        fn drop(&mut self) {
+            *self.dropped_on.lock().unwrap() = Some(thread::current().id());
+        }
+    }
+    let dropped_on = Arc::new(Mutex::new(None));
+    let owner = Owner {
+        words: vec!["one".to_owned(), "three".to_owned()],
+        dropped_on: dropped_on.clone(),
+    };
+    let r = ReadaheadBuilder::new(1)
+        .join_on_drop(None)
+        .spawn_owning(owner, |owner| {
+            Box::new(
+                owner
+                    .words
+                    .iter()
+                    .map(|word| (word.len(), thread::current().id())),
+            )
+        });
+    let items: Vec<_> = r.unwrap().collect();
+    assert_eq!(
+        items.iter().map(|(len, _)| *len).collect::<Vec<_>>(),
+        [3, 5]
+    );
+    let worker = items[0].1;
+    assert_ne!(worker, thread::current().id());
+    assert_eq!(*dropped_on.lock().unwrap(), Some(worker));
+}
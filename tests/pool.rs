@@ -0,0 +1,54 @@
+// Copyright 2021 Martin Pool
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use readahead_iterator::{Readahead, ReadaheadPool};
+
+/// A five-stage pipeline completes on a two-thread pool, and uses no more
+/// than two threads besides the consumer.
+#[test]
+fn deep_pipeline_on_small_pool() {
+    let pool = ReadaheadPool::new(2);
+    let threads = Arc::new(Mutex::new(HashSet::new()));
+    let mut stage: Readahead<u64> = pool.readahead(0..1000, 2);
+    for _ in 0..4 {
+        let threads = threads.clone();
+        stage = pool.readahead(
+            stage.map(move |i| {
+                threads.lock().unwrap().insert(thread::current().id());
+                i + 1
+            }),
+            2,
+        );
+    }
+    assert_eq!(stage.sum::<u64>(), (4..1004).sum());
+    let mut threads = threads.lock().unwrap();
+    threads.remove(&thread::current().id());
+    assert!(threads.len() <= 2);
+}
+
+#[test]
+fn pool_propagates_panic() {
+    let pool = ReadaheadPool::new(1);
+    let mut rah = pool.readahead((0..3).map(|i| if i < 1 { i } else { panic!("pooled") }), 2);
+    assert_eq!(rah.next(), Some(0));
+    let err = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| rah.next())).unwrap_err();
+    assert_eq!(*err.downcast_ref::<&str>().unwrap(), "pooled");
+}
+
+/// Readaheads keep working after the pool is dropped.
+#[test]
+fn readahead_outlives_pool() {
+    let pool = ReadaheadPool::new(1);
+    let rah = pool.readahead(0..100, 3);
+    drop(pool);
+    assert_eq!(rah.count(), 100);
+}
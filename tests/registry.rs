@@ -0,0 +1,70 @@
+// Copyright 2021 Martin Pool
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Tests for the process-wide worker registry.
+//!
+//! The registry is global, so everything is checked in one test, in its own
+//! test binary.
+
+#![cfg(feature = "registry")]
+
+use std::sync::{Arc, Mutex};
+use std::thread::sleep;
+use std::time::Duration;
+
+use readahead_iterator::{Readahead, ReadaheadBuilder, ReadaheadRegistry};
+
+#[test]
+fn registry_counts_and_queues_workers() {
+    assert_eq!(ReadaheadRegistry::live_workers(), 0);
+    let first = ReadaheadBuilder::new(1)
+        .join_on_drop(None)
+        .spawn(0..)
+        .unwrap();
+    sleep(Duration::from_millis(50));
+    assert_eq!(ReadaheadRegistry::live_workers(), 1);
+
+    // Going over the limit is reported once.
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let reports_clone = reports.clone();
+    ReadaheadRegistry::on_over_limit(move |live, limit| {
+        reports_clone.lock().unwrap().push((live, limit))
+    });
+    ReadaheadRegistry::set_soft_limit(1);
+    let extra = ReadaheadBuilder::new(1)
+        .join_on_drop(None)
+        .spawn(0..)
+        .unwrap();
+    sleep(Duration::from_millis(50));
+    assert_eq!(ReadaheadRegistry::live_workers(), 2);
+    assert_eq!(*reports.lock().unwrap(), [(2, 1)]);
+    drop(extra);
+    assert_eq!(ReadaheadRegistry::live_workers(), 1);
+
+    // Over the limit, a queued worker doesn't start until a slot is free.
+    ReadaheadRegistry::set_queue_over_limit(true);
+    let mut second = Readahead::new(10..12, 1);
+    sleep(Duration::from_millis(50));
+    assert_eq!(ReadaheadRegistry::live_workers(), 1);
+    drop(first);
+    assert_eq!(second.next(), Some(10));
+    assert_eq!(ReadaheadRegistry::live_workers(), 1);
+
+    // A panicking worker still deregisters.
+    ReadaheadRegistry::set_queue_over_limit(false);
+    ReadaheadRegistry::set_soft_limit(usize::MAX);
+    let panicking = ReadaheadBuilder::new(1)
+        .join_on_drop(None)
+        .spawn((0..1).map(|_| -> u32 { panic!("worker failed") }))
+        .unwrap();
+    drop(panicking);
+    assert_eq!(second.count(), 1);
+    sleep(Duration::from_millis(50));
+    assert_eq!(ReadaheadRegistry::live_workers(), 0);
+    assert_eq!(reports.lock().unwrap().len(), 1);
+}